@@ -0,0 +1,87 @@
+use color_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Hashes of the files the previous build was made from, stored in
+/// build-cache.json to skip stages whose inputs haven't changed.
+///
+/// Files are split by which outputs they affect: all outputs depend on the
+/// source FDB, mods.json, lookup.json and the packs, only the database depends
+/// on files included by SQL mods and only the locale on locale.xml and files
+/// included by locale texts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BuildCache {
+    /// Version of lu-mod-tool that made the build, since other versions may
+    /// produce different output from the same inputs.
+    #[serde(default)]
+    pub tool_version: String,
+    #[serde(default)]
+    pub inputs: BTreeMap<String, String>,
+    #[serde(default)]
+    pub database_inputs: BTreeMap<String, String>,
+    #[serde(default)]
+    pub locale_inputs: BTreeMap<String, String>,
+    /// Ids of the mods the build applied, for the changelog of the next one.
+    /// Missing in caches written before they were recorded.
+    #[serde(default)]
+    pub mods: Option<BTreeSet<String>>,
+}
+
+impl BuildCache {
+    /// Read the cache, treating a missing or unreadable file as an empty cache
+    /// so that everything is rebuilt.
+    pub fn read(path: &Path) -> BuildCache {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Whether the files all outputs depend on are exactly the ones the
+    /// previous build was made from, unchanged.
+    pub fn inputs_unchanged(&self, inputs: &[PathBuf]) -> bool {
+        self.tool_version == env!("CARGO_PKG_VERSION")
+            && self.inputs.len() == inputs.len()
+            && inputs
+                .iter()
+                .all(|path| self.inputs.contains_key(&path_key(path)))
+            && hashes_unchanged(&self.inputs)
+    }
+
+    pub fn database_inputs_unchanged(&self) -> bool {
+        hashes_unchanged(&self.database_inputs)
+    }
+
+    pub fn locale_inputs_unchanged(&self) -> bool {
+        hashes_unchanged(&self.locale_inputs)
+    }
+}
+
+/// Hash a list of files for the cache.
+pub fn hash_files(paths: &[PathBuf]) -> eyre::Result<BTreeMap<String, String>> {
+    paths
+        .iter()
+        .map(|path| Ok((path_key(path), hash_file(path)?)))
+        .collect()
+}
+
+fn hashes_unchanged(hashes: &BTreeMap<String, String>) -> bool {
+    hashes
+        .iter()
+        .all(|(path, hash)| matches!(hash_file(Path::new(path)), Ok(current) if &current == hash))
+}
+
+fn hash_file(path: &Path) -> eyre::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut file = std::fs::File::open(path)?;
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Paths relative to mods.json, with forward slashes.
+fn path_key(path: &Path) -> String {
+    let path = path.strip_prefix(".").unwrap_or(path);
+    path.to_string_lossy().replace('\\', "/")
+}
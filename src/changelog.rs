@@ -0,0 +1,151 @@
+use crate::lu_mod::{Mod, OutputValue};
+use crate::mod_context::ModContext;
+use assembly_fdb::core::Field;
+use color_eyre::eyre;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::Path;
+
+/// Write a Markdown changelog of a build: every row that got a database ID
+/// and every phrase added to the locale, grouped by pack and target table,
+/// followed by the mods with an ID that were added or removed since the
+/// previous build. `previous_mods` are the ids of the mods that build applied,
+/// if it recorded them, otherwise mods count as added if they didn't have an
+/// ID in `previous_lookup`.
+pub fn write_changelog(
+    mod_context: &ModContext,
+    previous_lookup: &BTreeMap<String, i32>,
+    previous_mods: Option<&BTreeSet<String>>,
+    path: &Path,
+) -> eyre::Result<()> {
+    let mut changelog = String::from("# Changelog\n");
+
+    for pack in &mod_context.packs {
+        let pack_mods = mod_context
+            .mods
+            .iter()
+            .filter(|m| &m.pack == pack)
+            .collect::<Vec<&Mod>>();
+        if pack_mods.is_empty() {
+            continue;
+        }
+        writeln!(changelog, "\n## {}", pack)?;
+
+        let mut table_names = Vec::<String>::new();
+        for lu_mod in &pack_mods {
+            let table_name = lu_mod.get_target_table_name();
+            if mod_context.lookup.contains_key(&lu_mod.id) && !table_names.contains(&table_name) {
+                table_names.push(table_name);
+            }
+        }
+        for table_name in table_names {
+            writeln!(changelog, "\n### {}\n", table_name)?;
+            writeln!(changelog, "| ID | Mod | Name |")?;
+            writeln!(changelog, "| ---: | --- | --- |")?;
+            for lu_mod in pack_mods
+                .iter()
+                .filter(|m| m.get_target_table_name() == table_name)
+            {
+                if let Some(id) = mod_context.lookup.get(&lu_mod.id) {
+                    let new = if previous_lookup.contains_key(&lu_mod.id) {
+                        ""
+                    } else {
+                        " (new)"
+                    };
+                    writeln!(
+                        changelog,
+                        "| {} | `{}`{} | {} |",
+                        id,
+                        lu_mod.id,
+                        new,
+                        escape(&english_name(lu_mod))
+                    )?;
+                }
+            }
+        }
+
+        let phrases = pack_mods
+            .iter()
+            .flat_map(|lu_mod| {
+                let id = mod_context.lookup.get(&lu_mod.id);
+                lu_mod.new_locale_entries.iter().map(move |phrase| {
+                    let phrase_id = match id {
+                        Some(id) => phrase.id.replace("{}", &id.to_string()),
+                        None => phrase.id.clone(),
+                    };
+                    let english = phrase
+                        .translations
+                        .iter()
+                        .find(|translation| translation.locale == "en_US")
+                        .map(|translation| translation.value.clone())
+                        .unwrap_or_default();
+                    (phrase_id, english)
+                })
+            })
+            .collect::<Vec<(String, String)>>();
+        if !phrases.is_empty() {
+            writeln!(changelog, "\n### Phrases\n")?;
+            writeln!(changelog, "| Phrase | English |")?;
+            writeln!(changelog, "| --- | --- |")?;
+            for (phrase_id, english) in phrases {
+                writeln!(changelog, "| `{}` | {} |", phrase_id, escape(&english))?;
+            }
+        }
+    }
+
+    // Compare the mods of this build with the ones of the previous build.
+    // lookup.json keeps the IDs of mods that are disabled or deleted, so it
+    // can't tell which mods were removed.
+    let mut added = mod_context
+        .mods
+        .iter()
+        .filter(|m| match previous_mods {
+            Some(previous_mods) => !previous_mods.contains(&m.id),
+            None => !previous_lookup.contains_key(&m.id),
+        })
+        .filter_map(|m| mod_context.lookup.get(&m.id).map(|id| (m.id.as_str(), *id)))
+        .collect::<Vec<(&str, i32)>>();
+    added.sort();
+    let removed = previous_mods
+        .into_iter()
+        .flatten()
+        .filter(|mod_id| !mod_context.mods.iter().any(|m| &m.id == *mod_id))
+        .filter_map(|mod_id| {
+            mod_context
+                .lookup
+                .get(mod_id)
+                .map(|id| (mod_id.as_str(), *id))
+        })
+        .collect::<Vec<(&str, i32)>>();
+
+    writeln!(changelog, "\n## Changes since the previous build")?;
+    for (title, entries) in [("Added", added), ("Removed", removed)] {
+        writeln!(changelog, "\n### {}\n", title)?;
+        if entries.is_empty() {
+            writeln!(changelog, "None.")?;
+        }
+        for (mod_id, id) in entries {
+            writeln!(changelog, "- `{}`: {}", mod_id, id)?;
+        }
+    }
+
+    std::fs::write(path, changelog)?;
+    Ok(())
+}
+
+/// The en_US name of a mod, or for icons their path.
+fn english_name(lu_mod: &Mod) -> String {
+    if let Some(name) = lu_mod.locale.get("en_US") {
+        return name.clone();
+    }
+    match lu_mod.output_values.get("IconPath") {
+        Some(OutputValue::Known(Field::Text(path))) => path.clone(),
+        Some(OutputValue::FromJson(serde_json::Value::String(path))) => path.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Escape text for a Markdown table cell.
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
@@ -0,0 +1,298 @@
+use color_eyre::{
+    eyre::{self, eyre},
+    Section,
+};
+use serde_json::Value as JsonValue;
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// The contents of a mod file, kept around to point errors at the part of the
+/// file they're about.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+impl SourceFile {
+    /// One-based line and column of a byte offset, with the column counted in
+    /// characters.
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let before = &self.contents[..floor_char_boundary(&self.contents, offset)];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        (line, before[line_start..].chars().count() + 1)
+    }
+
+    /// Create an error pointing at a byte offset of the file.
+    pub fn error_at(&self, message: impl fmt::Display, offset: usize) -> eyre::Report {
+        eyre!("{}", message).section(self.snippet(offset..offset + 1))
+    }
+
+    /// Add a snippet to an error that happened while applying the mod read from
+    /// `span`. If the error was caused by a value, the snippet points at that
+    /// value, otherwise at the id of the mod. Without a span, the error names
+    /// the file and the position of the mod in it.
+    pub fn diagnose(
+        &self,
+        error: eyre::Report,
+        index: usize,
+        span: Option<Range<usize>>,
+    ) -> eyre::Report {
+        let span = match span {
+            Some(span) => span,
+            None => {
+                return error.section(format!("--> {}, mod {}", self.path.display(), index + 1))
+            }
+        };
+        let value_error = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<ValueError>());
+        let highlight = match value_error {
+            Some(value_error) => self.locate(span, &value_error.key, Some(&value_error.value)),
+            None => self.locate(span, "id", None),
+        };
+        error.section(self.snippet(highlight))
+    }
+
+    /// Find a key, and the value after it, inside the span of a mod. Falls back
+    /// to the start of the mod if neither can be found.
+    fn locate(&self, span: Range<usize>, key: &str, value: Option<&JsonValue>) -> Range<usize> {
+        let text = &self.contents[span.clone()];
+        // JSON quotes keys, YAML and TOML usually don't
+        let key_range = [format!("\"{}\"", key), key.to_string()]
+            .iter()
+            .find_map(|candidate| {
+                text.find(candidate.as_str())
+                    .map(|i| i..i + candidate.len())
+            });
+
+        let search_start = key_range.as_ref().map(|range| range.end).unwrap_or(0);
+        let value_range = value.and_then(|value| {
+            let mut candidates = vec![value.to_string()];
+            if let JsonValue::String(text) = value {
+                candidates.push(text.clone());
+            }
+            candidates.iter().find_map(|candidate| {
+                text[search_start..]
+                    .find(candidate.as_str())
+                    .map(|i| search_start + i..search_start + i + candidate.len())
+            })
+        });
+
+        match value_range.or(key_range) {
+            Some(range) => span.start + range.start..span.start + range.end,
+            None => span.start..span.start + 1,
+        }
+    }
+
+    /// Render a snippet of the line containing the start of `span`, in the
+    /// style of rustc:
+    ///
+    /// ```text
+    ///   --> pirates/missions.json:12:17
+    ///    |
+    /// 12 |         "type": "Smassh",
+    ///    |                 ^^^^^^^^
+    /// ```
+    pub fn snippet(&self, span: Range<usize>) -> String {
+        // Spans can end inside a character, like one byte after an offset
+        let start = floor_char_boundary(&self.contents, span.start);
+        let end = ceil_char_boundary(&self.contents, span.end);
+        let (line, column) = self.line_column(start);
+        let line_start = self.contents[..start]
+            .rfind('\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let line_end = self.contents[line_start..]
+            .find('\n')
+            .map(|i| line_start + i)
+            .unwrap_or_else(|| self.contents.len());
+        let line_text = self.contents[line_start..line_end].trim_end_matches('\r');
+
+        // keep tabs in the marker line so the carets line up
+        let marker_indent = self.contents[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let marker_length = self.contents[start..end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        let gutter = " ".repeat(line.to_string().len());
+        format!(
+            "{gutter}--> {}:{}:{}\n{gutter} |\n{} | {}\n{gutter} | {}{}",
+            self.path.display(),
+            line,
+            column,
+            line,
+            line_text,
+            marker_indent,
+            "^".repeat(marker_length),
+            gutter = gutter,
+        )
+    }
+}
+
+/// Byte offset of a one-based line and column in `text`, where the column
+/// counts characters, as json5, serde_yaml and rhai report it.
+pub fn offset_of(text: &str, line: usize, column: usize) -> usize {
+    let line_start = line_start(text, line);
+    let line_text = text[line_start..]
+        .split_inclusive('\n')
+        .next()
+        .unwrap_or("");
+    line_start
+        + line_text
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map(|(i, _)| i)
+            .unwrap_or(line_text.len())
+}
+
+/// Byte offset of a one-based line and column in `text`, where the column
+/// counts bytes, as serde_json and toml report it.
+pub fn offset_of_byte_column(text: &str, line: usize, column: usize) -> usize {
+    let offset = line_start(text, line) + column.saturating_sub(1);
+    floor_char_boundary(text, offset)
+}
+
+fn line_start(text: &str, line: usize) -> usize {
+    text.split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum::<usize>()
+}
+
+/// The offset, or the start of the character it is in.
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// The offset, or the end of the character it is in.
+fn ceil_char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset += 1;
+    }
+    offset
+}
+
+/// Remove the "at line 3 column 5" that parsers add to their error messages,
+/// since the snippet shows the position.
+pub fn without_position(message: String) -> String {
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message,
+    }
+}
+
+/// An error caused by a specific value of a mod, which diagnostics point at.
+#[derive(Debug)]
+pub struct ValueError {
+    pub key: String,
+    pub value: JsonValue,
+    pub message: String,
+}
+
+impl ValueError {
+    pub fn new(key: &str, value: &JsonValue, message: impl Into<String>) -> ValueError {
+        ValueError {
+            key: key.to_string(),
+            value: value.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ValueError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(contents: &str) -> SourceFile {
+        SourceFile {
+            path: PathBuf::from("pirates/mods.yaml"),
+            contents: contents.to_string(),
+        }
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        let text = "a: 1\nname: \"Épée\" x\n";
+        // the x is the 14th character but the 16th byte of the line
+        let offset = offset_of(text, 2, 14);
+        assert_eq!(&text[offset..offset + 1], "x");
+        assert_eq!(source(text).line_column(offset), (2, 14));
+        assert_eq!(offset_of_byte_column(text, 2, 16), offset);
+        // byte columns inside a character go to its start
+        assert_eq!(&text[offset_of_byte_column(text, 2, 9)..][..2], "É");
+        // columns past the end of a line stay on that line
+        assert_eq!(offset_of(text, 1, 10), 5);
+    }
+
+    #[test]
+    fn snippets_at_multi_byte_characters() {
+        let file = source("id: sword\nlocale: { en_US: Épée }\n");
+        let offset = file.contents.find('É').unwrap();
+        let snippet = file.snippet(offset..offset + 1);
+        assert!(snippet.contains("pirates/mods.yaml:2:18"), "{}", snippet);
+        assert!(snippet.ends_with("|                  ^"), "{}", snippet);
+        // offsets inside a character are moved to its start
+        let inside = file.snippet(offset + 1..offset + 2);
+        assert_eq!(inside, snippet);
+        let _ = file.error_at("bad", offset + 1);
+        let end = file.contents.len();
+        assert!(file.snippet(end..end + 1).contains(":3:1"));
+    }
+
+    #[test]
+    fn yaml_errors_after_non_ascii_text() {
+        let text = "- id: épée\n  type: [é, \n";
+        let error = serde_yaml::from_str::<JsonValue>(text).unwrap_err();
+        let location = error.location().unwrap();
+        let file = source(text);
+        let offset = offset_of(text, location.line(), location.column());
+        assert!(text.is_char_boundary(offset));
+        let _ = file.error_at(without_position(error.to_string()), offset);
+    }
+
+    #[test]
+    fn locates_keys_and_values_of_mods() {
+        let file = source(
+            "- id: sword\n  values:\n    level: high\n- id: shield\n  values:\n    level: low\n",
+        );
+        let shield = file.contents.find("- id: shield").unwrap();
+        let high = file.locate(0..shield, "level", Some(&JsonValue::from("high")));
+        assert_eq!(&file.contents[high], "high");
+        let low = file.locate(
+            shield..file.contents.len(),
+            "level",
+            Some(&JsonValue::from("high")),
+        );
+        assert_eq!(&file.contents[low], "level");
+        let id = file.locate(shield..file.contents.len(), "id", None);
+        assert_eq!(file.line_column(id.start), (4, 3));
+
+        let json = source("[{\"id\": \"sword\", \"values\": {\"level\": 3}}]");
+        let level = json.locate(
+            1..json.contents.len() - 1,
+            "level",
+            Some(&JsonValue::from(3)),
+        );
+        assert_eq!(&json.contents[level], "3");
+    }
+}
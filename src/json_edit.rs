@@ -0,0 +1,309 @@
+use color_eyre::eyre::{self, eyre};
+use std::ops::Range;
+
+/// A JSON value parsed from text together with the bytes it spans, so that
+/// single values can be changed without reformatting the rest of the text.
+#[derive(Debug)]
+pub struct SpannedJson {
+    pub span: Range<usize>,
+    pub kind: SpannedKind,
+}
+
+#[derive(Debug)]
+pub enum SpannedKind {
+    Object(Vec<SpannedMember>),
+    Array(Vec<SpannedJson>),
+    String(String),
+    /// Numbers, booleans and null.
+    Other,
+}
+
+#[derive(Debug)]
+pub struct SpannedMember {
+    pub key: String,
+    pub key_span: Range<usize>,
+    pub value: SpannedJson,
+}
+
+impl SpannedJson {
+    pub fn parse(text: &str) -> eyre::Result<SpannedJson> {
+        let mut parser = Parser { text, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(parser.error("Unexpected text after the JSON value"));
+        }
+        Ok(value)
+    }
+
+    /// The value of a member, if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&SpannedJson> {
+        match &self.kind {
+            SpannedKind::Object(members) => members
+                .iter()
+                .find(|member| member.key == key)
+                .map(|member| &member.value),
+            _ => None,
+        }
+    }
+
+    /// An item, if this is an array that has it.
+    pub fn index(&self, index: usize) -> Option<&SpannedJson> {
+        match &self.kind {
+            SpannedKind::Array(items) => items.get(index),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.kind {
+            SpannedKind::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Replacements of parts of a text, applied all at once.
+#[derive(Debug, Default)]
+pub struct TextEdits {
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl TextEdits {
+    pub fn replace(&mut self, range: Range<usize>, text: String) {
+        self.edits.push((range, text));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    pub fn apply(mut self, text: &str) -> String {
+        let mut output = text.to_string();
+        // From the end, so that earlier ranges stay valid
+        self.edits
+            .sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+        for (range, replacement) in self.edits {
+            output.replace_range(range, &replacement);
+        }
+        output
+    }
+}
+
+/// Set string members of an object: existing members get the new value, new
+/// members are added after the last one, in the same layout.
+pub fn set_string_members(
+    text: &str,
+    object: &SpannedJson,
+    members: &[(String, String)],
+    edits: &mut TextEdits,
+) -> eyre::Result<()> {
+    let existing = match &object.kind {
+        SpannedKind::Object(existing) => existing,
+        _ => return Err(eyre!("Expected a JSON object")),
+    };
+    let mut added = String::new();
+    for (key, value) in members {
+        let value = serde_json::to_string(value)?;
+        if let Some(member) = existing.iter().find(|member| &member.key == key) {
+            edits.replace(member.value.span.clone(), value);
+            continue;
+        }
+        let key = serde_json::to_string(key)?;
+        match existing.last() {
+            Some(last) => {
+                let before_key = &text[..last.key_span.start];
+                let indent = &before_key[before_key.trim_end().len()..];
+                let indent = if indent.is_empty() { " " } else { indent };
+                let separator = &text[last.key_span.end..last.value.span.start];
+                added.push_str(&format!(",{}{}{}{}", indent, key, separator, value));
+            }
+            None => {
+                if !added.is_empty() {
+                    added.push_str(", ");
+                }
+                added.push_str(&format!("{}: {}", key, value));
+            }
+        }
+    }
+    if !added.is_empty() {
+        let at = match existing.last() {
+            Some(last) => last.value.span.end,
+            None => object.span.start + 1,
+        };
+        edits.replace(at..at, added);
+    }
+    Ok(())
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> eyre::Result<SpannedJson> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let kind = match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() == Some(b'}') {
+                        self.pos += 1;
+                        break;
+                    }
+                    if !members.is_empty() {
+                        self.expect(b',')?;
+                        self.skip_whitespace();
+                    }
+                    let key_start = self.pos;
+                    let key = self.string()?;
+                    let key_span = key_start..self.pos;
+                    self.skip_whitespace();
+                    self.expect(b':')?;
+                    let value = self.value()?;
+                    members.push(SpannedMember {
+                        key,
+                        key_span,
+                        value,
+                    });
+                }
+                SpannedKind::Object(members)
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() == Some(b']') {
+                        self.pos += 1;
+                        break;
+                    }
+                    if !items.is_empty() {
+                        self.expect(b',')?;
+                    }
+                    items.push(self.value()?);
+                }
+                SpannedKind::Array(items)
+            }
+            Some(b'"') => SpannedKind::String(self.string()?),
+            Some(_) => {
+                let length = self.text[self.pos..]
+                    .find(|c: char| matches!(c, ',' | '}' | ']') || c.is_whitespace())
+                    .unwrap_or(self.text.len() - self.pos);
+                if length == 0 {
+                    return Err(self.error("Expected a JSON value"));
+                }
+                self.pos += length;
+                SpannedKind::Other
+            }
+            None => return Err(self.error("Unexpected end of JSON")),
+        };
+        Ok(SpannedJson {
+            span: start..self.pos,
+            kind,
+        })
+    }
+
+    fn string(&mut self) -> eyre::Result<String> {
+        let start = self.pos;
+        self.expect(b'"')?;
+        let bytes = self.text.as_bytes();
+        while self.pos < bytes.len() && bytes[self.pos] != b'"' {
+            self.pos += if bytes[self.pos] == b'\\' { 2 } else { 1 };
+        }
+        self.expect(b'"')?;
+        serde_json::from_str(&self.text[start..self.pos])
+            .map_err(|error| self.error(&format!("Invalid string: {}", error)))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> eyre::Result<()> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("Expected `{}`", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error(&self, message: &str) -> eyre::Report {
+        eyre!("{} at byte {}", message, self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(text: &str, path: &[&str], members: &[(&str, &str)]) -> String {
+        let root = SpannedJson::parse(text).unwrap();
+        let mut object = &root;
+        for key in path {
+            object = match key.parse::<usize>() {
+                Ok(index) => object.index(index).unwrap(),
+                Err(_) => object.get(key).unwrap(),
+            };
+        }
+        let members = members
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        let mut edits = TextEdits::default();
+        set_string_members(text, object, &members, &mut edits).unwrap();
+        edits.apply(text)
+    }
+
+    #[test]
+    fn replaces_values_and_keeps_the_rest() {
+        let text = "[\n  {\n    \"id\": \"sword\",\n    \"damage\": 1.50,\n    \"locale\": {\n      \"en_US\": \"Sword\",\n      \"de_DE\": \"Schwert\"\n    }\n  }\n]\n";
+        let output = set(text, &["0", "locale"], &[("de_DE", "Klinge \"scharf\"")]);
+        assert_eq!(
+            output,
+            text.replace("\"Schwert\"", "\"Klinge \\\"scharf\\\"\"")
+        );
+    }
+
+    #[test]
+    fn appends_in_the_layout_of_the_last_member() {
+        let text = "{\n\t\"locale\": {\n\t\t\"en_US\": \"Épée\"\n\t},\n\t\"n\": 1e5\n}";
+        let output = set(
+            text,
+            &["locale"],
+            &[("fr_FR", "Épée"), ("de_DE", "Schwert")],
+        );
+        assert_eq!(
+            output,
+            "{\n\t\"locale\": {\n\t\t\"en_US\": \"Épée\",\n\t\t\"fr_FR\": \"Épée\",\n\t\t\"de_DE\": \"Schwert\"\n\t},\n\t\"n\": 1e5\n}"
+        );
+        assert_eq!(
+            set("{\"a\": {\"en_US\":\"x\"}}", &["a"], &[("de_DE", "y")]),
+            "{\"a\": {\"en_US\":\"x\", \"de_DE\":\"y\"}}"
+        );
+        assert_eq!(
+            set("{\"a\": {}}", &["a"], &[("de_DE", "y")]),
+            "{\"a\": {\"de_DE\": \"y\"}}"
+        );
+    }
+
+    #[test]
+    fn parses_escapes_and_rejects_invalid_json() {
+        let root = SpannedJson::parse(r#"{"a\"b": ["é\\", null, -2]}"#).unwrap();
+        let array = root.get("a\"b").unwrap();
+        assert_eq!(array.index(0).unwrap().as_str(), Some("é\\"));
+        assert!(array.index(1).unwrap().as_str().is_none());
+        assert!(SpannedJson::parse("{\"a\": 1").is_err());
+        assert!(SpannedJson::parse("{\"a\" 1}").is_err());
+        assert!(SpannedJson::parse("[1] 2").is_err());
+    }
+}
@@ -1,41 +1,663 @@
-use serde::{Deserialize, Serialize};
+use color_eyre::eyre::{self, WrapErr};
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename = "localization")]
+/// The locale of the client, locale.xml.
+///
+/// The full locale has well over a hundred thousand phrases, so only the
+/// locales and the ids of the phrases are read. When writing, the source file
+/// is copied event by event, keeping its layout, with the changes of locale
+/// mods applied and the new phrases appended in the order they were added.
+#[derive(Debug)]
 pub struct Localization {
-    pub version: f32,
-    pub locales: Locales,
-    pub phrases: Phrases,
+    source: PathBuf,
+    pub locales: Vec<String>,
+    /// Ids of the phrases of the source file, without the ones removed by mods.
+    phrase_ids: HashSet<String>,
+    /// Number of phrases in the source file.
+    phrase_count: usize,
+    new_phrases: Vec<Phrase>,
+    /// Changes to phrases of the source file by phrase id, in the order they
+    /// were made.
+    changes: HashMap<String, Vec<PhraseChange>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Locales {
-    pub count: usize,
-    pub locale: Vec<Locale>,
+#[derive(Debug, Clone)]
+pub enum PhraseChange {
+    /// Add a translation, or replace the one for the same locale.
+    Set(Translation),
+    /// Remove the translation for a locale.
+    RemoveTranslation(String),
+    /// Remove the whole phrase.
+    Remove,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Locale {
-    #[serde(rename = "$value", default)]
-    pub value: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Phrases {
-    pub count: usize,
-    pub phrase: Vec<Phrase>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Clone)]
 pub struct Phrase {
     pub id: String,
-    #[serde(rename = "translation")]
     pub translations: Vec<Translation>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Clone)]
 pub struct Translation {
     pub locale: String,
-    #[serde(rename = "$value", default)]
     pub value: String,
 }
+
+/// Whitespace the source file puts before phrases, translations and the end
+/// of phrases, taken from the first ones found. Used to write new phrases.
+#[derive(Default)]
+struct Indentation {
+    phrase: Option<Vec<u8>>,
+    translation: Option<Vec<u8>>,
+    phrase_end: Option<Vec<u8>>,
+}
+
+impl Localization {
+    /// Read the locales and phrase ids of a locale.xml.
+    pub fn read(path: &Path) -> eyre::Result<Localization> {
+        let mut reader = Reader::from_file(path)
+            .wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+        reader.trim_text(true);
+
+        let mut localization = Localization {
+            source: path.to_path_buf(),
+            locales: vec![],
+            phrase_ids: HashSet::new(),
+            phrase_count: 0,
+            new_phrases: vec![],
+            changes: HashMap::new(),
+        };
+        let mut in_locale = false;
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event(&mut buf)? {
+                Event::Start(e) if e.name() == b"locale" => in_locale = true,
+                Event::End(e) if e.name() == b"locale" => in_locale = false,
+                Event::Text(e) if in_locale => {
+                    localization.locales.push(e.unescape_and_decode(&reader)?);
+                }
+                Event::Start(e) | Event::Empty(e) if e.name() == b"phrase" => {
+                    let id = attribute(&reader, &e, b"id")?.unwrap_or_default();
+                    localization.phrase_ids.insert(id);
+                    localization.phrase_count += 1;
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        Ok(localization)
+    }
+
+    /// Whether the source file has a phrase, that no mod removed.
+    pub fn contains(&self, phrase_id: &str) -> bool {
+        self.phrase_ids.contains(phrase_id)
+    }
+
+    pub fn add_phrase(&mut self, phrase: Phrase) {
+        self.new_phrases.push(phrase);
+    }
+
+    /// Change a phrase of the source file.
+    pub fn change_phrase(&mut self, phrase_id: &str, change: PhraseChange) {
+        if let PhraseChange::Remove = change {
+            self.phrase_ids.remove(phrase_id);
+        }
+        self.changes
+            .entry(phrase_id.to_string())
+            .or_default()
+            .push(change);
+    }
+
+    /// The translations of some phrases as the source file has them, without
+    /// the changes of mods. Reads the whole file, so ask for all phrases at once.
+    pub fn source_translations(
+        &self,
+        phrase_ids: &HashSet<String>,
+    ) -> eyre::Result<HashMap<String, Vec<Translation>>> {
+        let mut reader = Reader::from_file(&self.source)
+            .wrap_err_with(|| format!("Failed to open {}", self.source.display()))?;
+        reader.trim_text(true);
+        let mut translations = HashMap::new();
+        let mut buf = Vec::new();
+        while translations.len() < phrase_ids.len() {
+            match reader.read_event(&mut buf)? {
+                Event::Start(e) if e.name() == b"phrase" => {
+                    let id = attribute(&reader, &e, b"id")?.unwrap_or_default();
+                    if phrase_ids.contains(&id) {
+                        let phrase = read_translations(&mut reader, &mut Indentation::default())?;
+                        translations.insert(id, phrase);
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        Ok(translations)
+    }
+
+    /// Write the source file with the changes and new phrases to `path`.
+    /// Everything mods don't touch is copied as it is, phrases that were
+    /// changed or added are written in the indentation of the source file.
+    pub fn write(&self, path: &Path) -> eyre::Result<()> {
+        let mut reader = Reader::from_file(&self.source)
+            .wrap_err_with(|| format!("Failed to open {}", self.source.display()))?;
+        let mut writer = Writer::new(BufWriter::new(File::create(path)?));
+        let removed = self
+            .changes
+            .values()
+            .filter(|changes| changes.iter().any(|c| matches!(c, PhraseChange::Remove)))
+            .count();
+        let phrase_count = self.phrase_count - removed + self.new_phrases.len();
+
+        let mut indentation = Indentation::default();
+        // Whitespace is held back until the next event is known, so that new
+        // phrases go before the whitespace in front of </phrases> and removed
+        // phrases take the whitespace in front of them along.
+        let mut whitespace: Option<BytesText<'static>> = None;
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event(&mut buf)? {
+                Event::Text(e) if e.iter().all(u8::is_ascii_whitespace) => {
+                    write_whitespace(&mut writer, &mut whitespace)?;
+                    whitespace = Some(e.into_owned());
+                }
+                Event::Start(e) if e.name() == b"phrases" => {
+                    write_whitespace(&mut writer, &mut whitespace)?;
+                    writer.write_event(Event::Start(with_count(&e, phrase_count)?))?;
+                }
+                Event::Empty(e) if e.name() == b"phrases" => {
+                    write_whitespace(&mut writer, &mut whitespace)?;
+                    let start = with_count(&e, phrase_count)?;
+                    if self.new_phrases.is_empty() {
+                        writer.write_event(Event::Empty(start))?;
+                    } else {
+                        writer.write_event(Event::Start(start))?;
+                        self.write_new_phrases(&mut writer, &indentation)?;
+                        writer.write_event(Event::End(BytesEnd::borrowed(b"phrases")))?;
+                    }
+                }
+                Event::End(e) if e.name() == b"phrases" => {
+                    self.write_new_phrases(&mut writer, &indentation)?;
+                    write_whitespace(&mut writer, &mut whitespace)?;
+                    writer.write_event(Event::End(e))?;
+                }
+                Event::Start(e) if e.name() == b"phrase" => {
+                    if indentation.phrase.is_none() {
+                        indentation.phrase = whitespace.as_ref().map(|w| w.to_vec());
+                    }
+                    let id = attribute(&reader, &e, b"id")?.unwrap_or_default();
+                    match self.changes.get(&id) {
+                        None => {
+                            write_whitespace(&mut writer, &mut whitespace)?;
+                            writer.write_event(Event::Start(e))?;
+                        }
+                        Some(changes) => {
+                            let start = e.into_owned();
+                            let translations = read_translations(&mut reader, &mut indentation)?;
+                            match apply_changes(translations, changes) {
+                                Some(translations) => {
+                                    write_whitespace(&mut writer, &mut whitespace)?;
+                                    write_phrase(&mut writer, start, &translations, &indentation)?;
+                                }
+                                None => whitespace = None,
+                            }
+                        }
+                    }
+                }
+                Event::Empty(e) if e.name() == b"phrase" => {
+                    let id = attribute(&reader, &e, b"id")?.unwrap_or_default();
+                    match self.changes.get(&id) {
+                        None => {
+                            write_whitespace(&mut writer, &mut whitespace)?;
+                            writer.write_event(Event::Empty(e))?;
+                        }
+                        Some(changes) => match apply_changes(vec![], changes) {
+                            Some(translations) => {
+                                write_whitespace(&mut writer, &mut whitespace)?;
+                                let start = e.into_owned();
+                                write_phrase(&mut writer, start, &translations, &indentation)?;
+                            }
+                            None => whitespace = None,
+                        },
+                    }
+                }
+                Event::Start(e) if e.name() == b"translation" => {
+                    if indentation.translation.is_none() {
+                        indentation.translation = whitespace.as_ref().map(|w| w.to_vec());
+                    }
+                    write_whitespace(&mut writer, &mut whitespace)?;
+                    writer.write_event(Event::Start(e))?;
+                }
+                Event::End(e) if e.name() == b"phrase" => {
+                    if indentation.phrase_end.is_none() {
+                        indentation.phrase_end = whitespace.as_ref().map(|w| w.to_vec());
+                    }
+                    write_whitespace(&mut writer, &mut whitespace)?;
+                    writer.write_event(Event::End(e))?;
+                }
+                Event::Eof => break,
+                event => {
+                    write_whitespace(&mut writer, &mut whitespace)?;
+                    writer.write_event(event)?;
+                }
+            }
+            buf.clear();
+        }
+        write_whitespace(&mut writer, &mut whitespace)?;
+        writer.into_inner().flush()?;
+        Ok(())
+    }
+
+    fn write_new_phrases<W: Write>(
+        &self,
+        writer: &mut Writer<W>,
+        indentation: &Indentation,
+    ) -> eyre::Result<()> {
+        for phrase in &self.new_phrases {
+            write_indentation(writer, &indentation.phrase)?;
+            let start = BytesStart::borrowed_name(b"phrase")
+                .with_attributes(vec![("id", phrase.id.as_str())]);
+            write_phrase(writer, start, &phrase.translations, indentation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the translations of a phrase up to its end.
+fn read_translations<R: BufRead>(
+    reader: &mut Reader<R>,
+    indentation: &mut Indentation,
+) -> eyre::Result<Vec<Translation>> {
+    let mut translations = vec![];
+    let mut whitespace = None;
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Text(e) if e.iter().all(u8::is_ascii_whitespace) => {
+                whitespace = Some(e.to_vec());
+                continue;
+            }
+            Event::Start(e) if e.name() == b"translation" => {
+                if indentation.translation.is_none() {
+                    indentation.translation = whitespace.clone();
+                }
+                let locale = attribute(reader, &e, b"locale")?.unwrap_or_default();
+                let value = reader.read_text(b"translation", &mut Vec::new())?;
+                translations.push(Translation { locale, value });
+            }
+            Event::Empty(e) if e.name() == b"translation" => {
+                let locale = attribute(reader, &e, b"locale")?.unwrap_or_default();
+                translations.push(Translation {
+                    locale,
+                    value: String::new(),
+                });
+            }
+            Event::End(e) if e.name() == b"phrase" => {
+                if indentation.phrase_end.is_none() {
+                    indentation.phrase_end = whitespace.clone();
+                }
+                return Ok(translations);
+            }
+            Event::Eof => return Err(eyre::eyre!("Unexpected end of locale.xml in a phrase")),
+            _ => {}
+        }
+        whitespace = None;
+        buf.clear();
+    }
+}
+
+/// Apply the changes of locale mods to the translations of a phrase. Returns
+/// None if the phrase was removed.
+fn apply_changes(
+    mut translations: Vec<Translation>,
+    changes: &[PhraseChange],
+) -> Option<Vec<Translation>> {
+    for change in changes {
+        match change {
+            PhraseChange::Set(new) => {
+                match translations.iter_mut().find(|t| t.locale == new.locale) {
+                    Some(translation) => translation.value = new.value.clone(),
+                    None => translations.push(new.clone()),
+                }
+            }
+            PhraseChange::RemoveTranslation(locale) => translations.retain(|t| &t.locale != locale),
+            PhraseChange::Remove => return None,
+        }
+    }
+    Some(translations)
+}
+
+fn write_phrase<W: Write>(
+    writer: &mut Writer<W>,
+    start: BytesStart,
+    translations: &[Translation],
+    indentation: &Indentation,
+) -> eyre::Result<()> {
+    writer.write_event(Event::Start(start))?;
+    for translation in translations {
+        write_indentation(writer, &indentation.translation)?;
+        writer.write_event(Event::Start(
+            BytesStart::borrowed_name(b"translation")
+                .with_attributes(vec![("locale", translation.locale.as_str())]),
+        ))?;
+        writer.write_event(Event::Text(BytesText::from_plain_str(&translation.value)))?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"translation")))?;
+    }
+    write_indentation(writer, &indentation.phrase_end)?;
+    writer.write_event(Event::End(BytesEnd::borrowed(b"phrase")))?;
+    Ok(())
+}
+
+fn write_indentation<W: Write>(
+    writer: &mut Writer<W>,
+    indentation: &Option<Vec<u8>>,
+) -> eyre::Result<()> {
+    if let Some(indentation) = indentation {
+        writer.write_event(Event::Text(BytesText::from_escaped(indentation.as_slice())))?;
+    }
+    Ok(())
+}
+
+fn write_whitespace<W: Write>(
+    writer: &mut Writer<W>,
+    whitespace: &mut Option<BytesText<'static>>,
+) -> eyre::Result<()> {
+    if let Some(whitespace) = whitespace.take() {
+        writer.write_event(Event::Text(whitespace))?;
+    }
+    Ok(())
+}
+
+/// The value of an attribute of an element, unescaped.
+fn attribute<R: BufRead>(
+    reader: &Reader<R>,
+    element: &BytesStart,
+    name: &[u8],
+) -> eyre::Result<Option<String>> {
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        if attribute.key == name {
+            return Ok(Some(attribute.unescape_and_decode_value(reader)?));
+        }
+    }
+    Ok(None)
+}
+
+/// A copy of an element with its `count` attribute set, keeping the other
+/// attributes in their order.
+fn with_count(element: &BytesStart, count: usize) -> eyre::Result<BytesStart<'static>> {
+    let mut copy = BytesStart::owned_name(element.name().to_vec());
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        if attribute.key == b"count" {
+            copy.push_attribute(("count", count.to_string().as_str()));
+        } else {
+            copy.push_attribute(attribute);
+        }
+    }
+    Ok(copy)
+}
+
+impl Phrase {
+    /// Add a translation for every locale in `locales` that the phrase doesn't
+    /// have, copied from the `fallback` locale. Returns the locales that were
+    /// filled in, nothing if the phrase has no fallback translation either.
+    pub fn fill_missing_locales(&mut self, locales: &[String], fallback: &str) -> Vec<String> {
+        let fallback_value = match self.translations.iter().find(|t| t.locale == fallback) {
+            Some(translation) => translation.value.clone(),
+            None => return vec![],
+        };
+        let mut filled = vec![];
+        for locale in locales {
+            if !self.translations.iter().any(|t| &t.locale == locale) {
+                self.translations.push(Translation {
+                    locale: locale.clone(),
+                    value: fallback_value.clone(),
+                });
+                filled.push(locale.clone());
+            }
+        }
+        filled
+    }
+
+    /// Problems of the translations that would only show up in game: `%[...]`
+    /// placeholders, `\n` escapes or markup tags that differ from the text of
+    /// the source locale, and characters that aren't allowed in XML.
+    pub fn translation_problems(&self, source_locale: &str) -> Vec<String> {
+        let mut problems = vec![];
+        let source = self.translations.iter().find(|t| t.locale == source_locale);
+        for translation in &self.translations {
+            if let Some(c) = translation.value.chars().find(|c| !is_xml_char(*c)) {
+                problems.push(format!(
+                    "the {} text contains the character U+{:04X}, which isn't allowed in XML",
+                    translation.locale, c as u32
+                ));
+            }
+            let source = match source {
+                Some(source) if translation.locale != source_locale => source,
+                _ => continue,
+            };
+            let checks = [
+                (
+                    "placeholders",
+                    placeholders(&source.value),
+                    placeholders(&translation.value),
+                ),
+                (
+                    "line breaks",
+                    line_breaks(&source.value),
+                    line_breaks(&translation.value),
+                ),
+                (
+                    "markup tags",
+                    markup_tags(&source.value),
+                    markup_tags(&translation.value),
+                ),
+            ];
+            for (name, expected, found) in checks {
+                if expected != found {
+                    problems.push(format!(
+                        "the {} text has the {} [{}], but the {} text has [{}]",
+                        translation.locale,
+                        name,
+                        found.join(", "),
+                        source_locale,
+                        expected.join(", ")
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// Characters allowed in XML 1.0 documents.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..='\u{10FFFF}')
+}
+
+/// The `%[...]` placeholders in a text, sorted.
+fn placeholders(text: &str) -> Vec<String> {
+    let mut found = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find("%[") {
+        match rest[start..].find(']') {
+            Some(end) => {
+                found.push(rest[start..start + end + 1].to_string());
+                rest = &rest[start + end + 1..];
+            }
+            None => break,
+        }
+    }
+    found.sort();
+    found
+}
+
+/// The `\n` escapes in a text, which the client shows as line breaks.
+fn line_breaks(text: &str) -> Vec<String> {
+    text.matches("\\n").map(String::from).collect()
+}
+
+/// The names of the markup tags in a text, like `font` and `/font`, sorted.
+fn markup_tags(text: &str) -> Vec<String> {
+    let mut found = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        match rest[start..].find('>') {
+            Some(end) => {
+                let tag = &rest[start + 1..start + end];
+                let name = tag.split_whitespace().next().unwrap_or_default();
+                found.push(name.trim_end_matches('/').to_lowercase());
+                rest = &rest[start + end + 1..];
+            }
+            None => break,
+        }
+    }
+    found.sort();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCALE_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<localization version="1.2">
+ <locales count="2">
+  <locale>en_US</locale>
+  <locale>de_DE</locale>
+ </locales>
+ <phrases count="3">
+  <phrase id="greeting">
+   <translation locale="en_US">Hello %[name]!</translation>
+   <translation locale="de_DE">Hallo %[name]!</translation>
+  </phrase>
+  <phrase id="farewell">
+   <translation locale="en_US">Bye</translation>
+  </phrase>
+  <phrase id="empty"/>
+ </phrases>
+</localization>
+"#;
+
+    /// Write `contents` to a file in a new temporary directory.
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lu-mod-tool-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("locale.xml");
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn phrase(translations: &[(&str, &str)]) -> Phrase {
+        Phrase {
+            id: String::from("test"),
+            translations: translations
+                .iter()
+                .map(|(locale, value)| Translation {
+                    locale: locale.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn finds_placeholders_line_breaks_and_tags() {
+        assert_eq!(
+            placeholders("%[b] and %[a] and %[unclosed"),
+            vec!["%[a]", "%[b]"]
+        );
+        assert!(placeholders("100% sure").is_empty());
+        assert_eq!(line_breaks("one\\ntwo\\nthree"), vec!["\\n", "\\n"]);
+        assert!(line_breaks("one\ntwo").is_empty());
+        assert_eq!(
+            markup_tags("<FONT color=\"red\">Épée</font><br/> <unclosed"),
+            vec!["/font", "br", "font"]
+        );
+    }
+
+    #[test]
+    fn reports_translation_problems() {
+        let problems = phrase(&[
+            ("en_US", "<font>Hello %[name]!</font>\\n"),
+            ("de_DE", "<font>Hallo %[nom]!</font>"),
+            ("fr_FR", "<font>Bonjour %[name]!</font>\\n"),
+            ("en_GB", "Hello\u{1}"),
+        ])
+        .translation_problems("en_US");
+        assert_eq!(problems.len(), 6, "{:?}", problems);
+        assert!(problems[0].contains("de_DE text has the placeholders [%[nom]]"));
+        assert!(problems[1].contains("de_DE text has the line breaks []"));
+        assert!(problems[2].contains("U+0001"));
+        assert!(phrase(&[("de_DE", "%[x]")])
+            .translation_problems("en_US")
+            .is_empty());
+    }
+
+    #[test]
+    fn reads_source_translations() {
+        let path = temp_file("source", LOCALE_XML);
+        let localization = Localization::read(&path).unwrap();
+        let ids = ["greeting", "empty", "missing"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<HashSet<String>>();
+        let translations = localization.source_translations(&ids).unwrap();
+        assert_eq!(translations.len(), 1);
+        assert_eq!(translations["greeting"][1].locale, "de_DE");
+        assert_eq!(translations["greeting"][1].value, "Hallo %[name]!");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn writes_changes_and_keeps_the_rest() {
+        let path = temp_file("write", LOCALE_XML);
+        let mut localization = Localization::read(&path).unwrap();
+        assert_eq!(localization.locales, vec!["en_US", "de_DE"]);
+        assert!(localization.contains("farewell"));
+
+        localization.change_phrase(
+            "greeting",
+            PhraseChange::Set(Translation {
+                locale: String::from("de_DE"),
+                value: String::from("Grüß dich, %[name] & co!"),
+            }),
+        );
+        localization.change_phrase("greeting", PhraseChange::RemoveTranslation("en_US".into()));
+        localization.change_phrase("farewell", PhraseChange::Remove);
+        assert!(!localization.contains("farewell"));
+        localization.add_phrase(phrase(&[("en_US", "New"), ("de_DE", "Neu")]));
+
+        let output = path.with_file_name("output.xml");
+        localization.write(&output).unwrap();
+        // one phrase removed and one added, so the count stays 3
+        let expected = LOCALE_XML
+            .replace(
+                "   <translation locale=\"en_US\">Hello %[name]!</translation>\n   <translation locale=\"de_DE\">Hallo %[name]!</translation>",
+                "   <translation locale=\"de_DE\">Grüß dich, %[name] &amp; co!</translation>",
+            )
+            .replace(
+                "  <phrase id=\"farewell\">\n   <translation locale=\"en_US\">Bye</translation>\n  </phrase>\n",
+                "",
+            )
+            .replace(
+                "  <phrase id=\"empty\"/>\n",
+                "  <phrase id=\"empty\"/>\n  <phrase id=\"test\">\n   <translation locale=\"en_US\">New</translation>\n   <translation locale=\"de_DE\">Neu</translation>\n  </phrase>\n",
+            );
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), expected);
+
+        // the output can be read again
+        let written = Localization::read(&output).unwrap();
+        assert_eq!(written.phrase_count, 3);
+        assert!(written.contains("test") && !written.contains("farewell"));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
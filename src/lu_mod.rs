@@ -1,5 +1,10 @@
+use crate::component::component_name_to_id;
+use crate::diagnostics::ValueError;
+use crate::locale::PhraseChange;
 use crate::mission::parse_mission_task_type;
+use crate::mission::parse_prerequisites;
 use crate::mission::MissionOffer;
+use crate::mission::MissionRewards;
 use crate::mission::MissionTask;
 use crate::mod_type_to_table_name;
 use crate::ModContext;
@@ -7,12 +12,15 @@ use crate::Phrase;
 use crate::Translation;
 use assembly_fdb::common::ValueType;
 use assembly_fdb::core::Field;
-use color_eyre::eyre::{self, eyre};
+use color_eyre::{
+    eyre::{self, eyre, WrapErr},
+    Section,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{to_value as to_json_value, Value as JsonValue};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Mod {
@@ -26,25 +34,35 @@ pub struct Mod {
     pub components: Vec<String>,
     pub table: Option<String>,
     #[serde(default)]
-    items: Vec<JsonValue>,
+    pub items: Vec<JsonValue>,
     #[serde(default)]
-    skills: Vec<JsonValue>,
+    pub skills: Vec<JsonValue>,
     #[serde(default)]
-    tasks: Vec<MissionTask>,
+    pub tasks: Vec<MissionTask>,
     #[serde(default)]
-    missions: Vec<MissionOffer>,
-    pub locale: HashMap<String, String>,
-    pub values: HashMap<String, serde_json::Value>,
+    pub missions: Vec<MissionOffer>,
+    pub locale: BTreeMap<String, String>,
+    pub values: BTreeMap<String, serde_json::Value>,
     #[serde(default, skip)]
-    pub output_values: HashMap<String, OutputValue>,
+    pub output_values: BTreeMap<String, OutputValue>,
     #[serde(skip)]
-    pub defaults: HashMap<String, Field>,
+    pub defaults: BTreeMap<String, Field>,
     #[serde(skip)]
     pub fields: Vec<OutputValue>,
     #[serde(skip)]
     pub dir: PathBuf,
+    /// Name of the pack this mod belongs to.
+    #[serde(skip)]
+    pub pack: String,
     #[serde(skip)]
     pub new_locale_entries: Vec<Phrase>,
+    /// Files included with `INCLUDE:`, which are inputs of the build.
+    #[serde(skip)]
+    pub includes: Vec<PathBuf>,
+    /// Files included with `INCLUDE:` in locale texts, which are inputs of the
+    /// locale only.
+    #[serde(skip)]
+    pub locale_includes: Vec<PathBuf>,
 }
 
 impl Mod {
@@ -54,6 +72,7 @@ impl Mod {
     {
         if self.values.get(key).is_none() {
             let value = to_json_value(value)?;
+            self.defaults.insert(key.to_string(), json_to_field(&value));
             self.output_values
                 .insert(key.to_string(), OutputValue::FromJson(value));
         }
@@ -65,6 +84,8 @@ impl Mod {
         T: serde::Serialize,
     {
         let value = to_json_value(value)?;
+        // the value replaces the default, if there was one
+        self.defaults.remove(key);
         self.output_values
             .insert(key.to_string(), OutputValue::FromJson(value));
         Ok(())
@@ -84,6 +105,12 @@ impl Mod {
         Ok(())
     }
 
+    fn set_awaiting_ids(&mut self, key: &str, parts: Vec<IdPart>) -> eyre::Result<()> {
+        self.output_values
+            .insert(key.to_string(), OutputValue::AwaitingIds(parts));
+        Ok(())
+    }
+
     pub fn init_output_values(&mut self) {
         for (key, value) in self.values.iter() {
             self.output_values
@@ -93,7 +120,11 @@ impl Mod {
 
     /// Generate the fields for DB insertion for this mod.
     fn set_fields(&mut self, mod_context: &ModContext) -> eyre::Result<()> {
-        let table_name = mod_type_to_table_name(self.mod_type.as_str());
+        let table_name = self.get_target_table_name();
+        if self.table.is_some() {
+            // explicitly requested tables have to exist
+            get_table_or_suggest(&mod_context.database, &table_name)?;
+        }
         for src_table in mod_context.database.tables()?.iter() {
             let src_table = src_table?;
             if src_table.name() == table_name {
@@ -104,6 +135,14 @@ impl Mod {
                         *text = convert_path_specifier(self, text);
                     }
                 }
+                // replace the guessed types of defaults with the column types
+                for (column, field) in src_table.column_iter().zip(fields.iter()) {
+                    if let Some(default) = self.defaults.get_mut(column.name().as_ref()) {
+                        if let OutputValue::Known(field) = field {
+                            *default = field.clone();
+                        }
+                    }
+                }
                 self.fields = fields;
                 return Ok(());
             }
@@ -111,8 +150,24 @@ impl Mod {
         Ok(())
     }
 
+    /// Where the value for a column of this mod came from, if it was set at all.
+    pub fn value_source(&self, key: &str) -> Option<ValueSource> {
+        match self.output_values.get(key)? {
+            OutputValue::GenerateId => Some(ValueSource::Generated),
+            OutputValue::AwaitingId(_) | OutputValue::AwaitingIds(_) => Some(ValueSource::Awaited),
+            _ if self.defaults.contains_key(key) => Some(ValueSource::Default),
+            _ if self.values.contains_key(key) => Some(ValueSource::Mod),
+            _ => Some(ValueSource::Fixed),
+        }
+    }
+
+    /// The table this mod adds a row to: `table` if the mod sets it, otherwise
+    /// derived from the mod type.
     pub fn get_target_table_name(&self) -> String {
-        mod_type_to_table_name(&self.mod_type)
+        match &self.table {
+            Some(table) => table.clone(),
+            None => mod_type_to_table_name(&self.mod_type),
+        }
     }
 
     /// Create component, register it in the mod_context and link it to this mod.
@@ -126,6 +181,7 @@ impl Mod {
         let mut output = Mod {
             id: id_str,
             mod_type: component_type.to_string(),
+            table: None,
             ..self.clone()
         };
         apply_component_mod(mod_context, &mut output)?;
@@ -133,37 +189,69 @@ impl Mod {
         Ok(output)
     }
 
-    pub fn add_locale(&mut self, phrase_id: &str) {
+    pub fn add_locale(&mut self, phrase_id: &str) -> eyre::Result<()> {
         if !self.locale.is_empty() {
-            let phrase = Phrase {
+            let mut translations = vec![];
+            for (locale, value) in self.locale.clone() {
+                translations.push(Translation {
+                    value: self.locale_text(&locale, &value)?,
+                    locale,
+                });
+            }
+            self.new_locale_entries.push(Phrase {
                 id: phrase_id.to_string(),
-                translations: self
-                    .locale
-                    .iter()
-                    .map(|(k, v)| Translation {
-                        locale: k.clone(),
-                        value: v.clone(),
-                    })
-                    .collect::<Vec<Translation>>(),
-            };
-            self.new_locale_entries.push(phrase);
+                translations,
+            });
         }
+        Ok(())
     }
 
-    pub fn add_locale_from_value(&mut self, phrase_id: &str, key: &str) {
-        if let Some(serde_json::Value::Object(value)) = self.values.get(key) {
-            let phrase = Phrase {
+    pub fn add_locale_from_value(&mut self, phrase_id: &str, key: &str) -> eyre::Result<()> {
+        if let Some(serde_json::Value::Object(value)) = self.values.get(key).cloned() {
+            let mut translations = vec![];
+            for (locale, text) in value {
+                let text = match text.as_str() {
+                    Some(text) => self.locale_text(&locale, text)?,
+                    None => {
+                        return Err(ValueError::new(
+                            &format!("{}.{}", key, locale),
+                            &text,
+                            format!("The {} text of {} needs to be a string", locale, key),
+                        )
+                        .into())
+                    }
+                };
+                translations.push(Translation {
+                    locale,
+                    value: text,
+                });
+            }
+            self.new_locale_entries.push(Phrase {
                 id: phrase_id.to_string(),
-                translations: value
-                    .iter()
-                    .map(|(k, v)| Translation {
-                        locale: k.clone(),
-                        value: v.as_str().unwrap().to_string(), // will crash if bad
-                    })
-                    .collect::<Vec<Translation>>(),
-            };
-            self.new_locale_entries.push(phrase);
+                translations,
+            });
         }
+        Ok(())
+    }
+
+    /// The text of a translation. Texts like `INCLUDE:dialogue/{locale}/offer.txt`
+    /// are read from that file instead, relative to the mod and with `{locale}`
+    /// replaced by the locale of the translation.
+    fn locale_text(&mut self, locale: &str, value: &str) -> eyre::Result<String> {
+        let path = match locale_include(&self.dir, locale, value) {
+            Some(path) => path,
+            None => return Ok(value.to_string()),
+        };
+        let text = std::fs::read_to_string(&path).wrap_err_with(|| {
+            format!(
+                "Failed to read the {} text of mod {} from {}",
+                locale,
+                self.id,
+                path.display()
+            )
+        })?;
+        self.locale_includes.push(path);
+        Ok(text.trim_end_matches(&['\r', '\n'][..]).to_string())
     }
 
     fn link_skills(&mut self, mod_context: &mut ModContext) -> eyre::Result<()> {
@@ -195,6 +283,12 @@ impl Mod {
     }
 }
 
+/// The file a locale text includes with `INCLUDE:`, if it does.
+pub fn locale_include(dir: &Path, locale: &str, value: &str) -> Option<PathBuf> {
+    let path = value.strip_prefix("INCLUDE:")?;
+    Some(dir.join(path.replace("{locale}", locale)))
+}
+
 impl Default for Mod {
     fn default() -> Self {
         Self {
@@ -208,13 +302,16 @@ impl Default for Mod {
             skills: vec![],
             tasks: vec![],
             missions: vec![],
-            locale: HashMap::new(),
-            values: HashMap::new(),
-            output_values: HashMap::new(),
-            defaults: HashMap::new(),
+            locale: BTreeMap::new(),
+            values: BTreeMap::new(),
+            output_values: BTreeMap::new(),
+            defaults: BTreeMap::new(),
             fields: vec![],
             dir: PathBuf::new(),
+            pack: String::new(),
             new_locale_entries: vec![],
+            includes: vec![],
+            locale_includes: vec![],
         }
     }
 }
@@ -224,9 +321,73 @@ pub enum OutputValue {
     Known(Field),
     FromJson(JsonValue),
     AwaitingId(String),
+    /// Text that contains the IDs of other mods, like the prerequisites of a
+    /// mission, put together once the IDs are known.
+    AwaitingIds(Vec<IdPart>),
     GenerateId,
 }
 
+/// Part of an `OutputValue::AwaitingIds` text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdPart {
+    Text(String),
+    /// The id of a mod, replaced by its database ID.
+    Id(String),
+}
+
+/// Origin of a value in the row generated for a mod, used by show-defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSource {
+    /// Given in the mod's `values`.
+    Mod,
+    /// Filled in by `set_default` because the mod didn't set it.
+    Default,
+    /// Always set by the mod type, regardless of the mod's `values`.
+    Fixed,
+    /// A newly generated (or previously generated) database ID.
+    Generated,
+    /// The ID of another mod, looked up after IDs were generated.
+    Awaited,
+}
+
+impl std::fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ValueSource::Mod => "mod",
+            ValueSource::Default => "default",
+            ValueSource::Fixed => "fixed",
+            ValueSource::Generated => "generated",
+            ValueSource::Awaited => "lookup",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Best guess of the field type of a JSON value, before the column type is known.
+fn json_to_field(value: &JsonValue) -> Field {
+    match value {
+        JsonValue::Bool(b) => Field::Boolean(*b),
+        JsonValue::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Field::Integer(i as i32)
+            } else {
+                Field::Float(n.as_f64().unwrap_or_default() as f32)
+            }
+        }
+        JsonValue::String(s) => Field::Text(s.clone()),
+        _ => Field::Nothing,
+    }
+}
+
+/// Text for a text column. Numbers and booleans are accepted too, since formats
+/// like CSV can't tell `93` the number from `93` the text.
+fn json_to_text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
 pub fn convert_path_specifier(lu_mod: &Mod, contents: &str) -> String {
     if let Some(asset_path) = contents.strip_prefix("ASSET:") {
         let mut relative_path_to_mods = "../mods";
@@ -292,6 +453,7 @@ pub fn apply_sql_mod(_mod_context: &ModContext, lu_mod: &mut Mod) -> eyre::Resul
             if let Some(path) = sql_str.strip_prefix("INCLUDE:") {
                 // load from path
                 let mut sql_file = std::fs::File::open(lu_mod.dir.join(path))?;
+                lu_mod.includes.push(lu_mod.dir.join(path));
                 let mut sql_str = String::new();
                 sql_file.read_to_string(&mut sql_str)?;
                 lu_mod.values.insert(String::from("sql"), sql_str.into());
@@ -344,7 +506,50 @@ pub fn apply_environmental_mod(mod_context: &mut ModContext, lu_mod: &mut Mod) -
     Ok(())
 }
 
+/// Phrase ids of the texts of mods, where `{}` is replaced by the database ID
+/// of the mod. Object-like mods add their `locale` as `OBJECT_NAME_PHRASE`,
+/// mission mods as `MISSION_NAME_PHRASE`.
+pub const OBJECT_NAME_PHRASE: &str = "Objects_{}_name";
+pub const MISSION_NAME_PHRASE: &str = "Missions_{}_name";
+/// The `locale` of a mission task, `{}` is the ID of the task.
+pub const MISSION_TASK_PHRASE: &str = "MissionTasks_{}_description";
+
+/// The localized values of mission mods and the phrases they're added as.
+/// Some phrases can be given with either of two keys.
+pub const MISSION_TEXT_PHRASES: &[(&str, &str)] = &[
+    ("MissionText_{}_accept_chat_bubble", "accept_chat_bubble"),
+    ("MissionText_{}_accept_chat_bubble", "chat_accept"),
+    ("MissionText_{}_chat_state_1", "chat_state_1"),
+    ("MissionText_{}_chat_state_2", "chat_state_2"),
+    ("MissionText_{}_chat_state_3", "chat_state_3"),
+    ("MissionText_{}_chat_state_4", "chat_state_4"),
+    ("MissionText_{}_chat_state_1", "chat_available"),
+    ("MissionText_{}_chat_state_2", "chat_active"),
+    ("MissionText_{}_chat_state_3", "chat_ready_to_complete"),
+    ("MissionText_{}_chat_state_4", "chat_complete"),
+    (
+        "MissionText_{}_completion_succeed_tip",
+        "completion_succeed_tip",
+    ),
+    ("MissionText_{}_in_progress", "in_progress"),
+    ("MissionText_{}_offer", "offer"),
+    ("MissionText_{}_ready_to_complete", "ready_to_complete"),
+];
+
+/// The phrase the `locale` of a mod is added as, if its type adds one.
+pub fn name_phrase_id(mod_type: &str) -> Option<&'static str> {
+    match mod_type {
+        "object" | "item" | "environmental" | "npc" | "enemy" => Some(OBJECT_NAME_PHRASE),
+        "mission" => Some(MISSION_NAME_PHRASE),
+        _ => None,
+    }
+}
+
 pub fn apply_mission_mod(mod_context: &mut ModContext, lu_mod: &mut Mod) -> eyre::Result<()> {
+    if let Some(rewards) = lu_mod.values.get("rewards").cloned() {
+        set_mission_rewards(lu_mod, &rewards)?;
+    }
+
     lu_mod.set_default("locStatus", 2)?;
     lu_mod.set_default("UIPrereqID", JsonValue::Null)?;
     lu_mod.set_default("localize", true)?;
@@ -369,27 +574,26 @@ pub fn apply_mission_mod(mod_context: &mut ModContext, lu_mod: &mut Mod) -> eyre
     lu_mod.set_default("reward_currency_repeatable", 0)?;
     lu_mod.set_to_be_generated("id")?;
 
+    // Prerequisites by mod id, instead of the database IDs in prereqMissionID
+    if let Some(prerequisites) = lu_mod.values.get("prerequisites").cloned() {
+        if lu_mod.values.contains_key("prereqMissionID") {
+            return Err(ValueError::new(
+                "prerequisites",
+                &prerequisites,
+                "A mission can't set both prerequisites and prereqMissionID",
+            ))
+            .suggestion("Remove prereqMissionID, prerequisites can contain database IDs too.");
+        }
+        lu_mod.set_awaiting_ids("prereqMissionID", parse_prerequisites(&prerequisites)?)?;
+    }
+
     lu_mod.set_fields(mod_context)?;
 
     // Locale
-    lu_mod.add_locale("Missions_{}_name");
-    lu_mod.add_locale_from_value("MissionText_{}_accept_chat_bubble", "accept_chat_bubble");
-    lu_mod.add_locale_from_value("MissionText_{}_accept_chat_bubble", "chat_accept");
-    lu_mod.add_locale_from_value("MissionText_{}_chat_state_1", "chat_state_1");
-    lu_mod.add_locale_from_value("MissionText_{}_chat_state_2", "chat_state_2");
-    lu_mod.add_locale_from_value("MissionText_{}_chat_state_3", "chat_state_3");
-    lu_mod.add_locale_from_value("MissionText_{}_chat_state_4", "chat_state_4");
-    lu_mod.add_locale_from_value("MissionText_{}_chat_state_1", "chat_available");
-    lu_mod.add_locale_from_value("MissionText_{}_chat_state_2", "chat_active");
-    lu_mod.add_locale_from_value("MissionText_{}_chat_state_3", "chat_ready_to_complete");
-    lu_mod.add_locale_from_value("MissionText_{}_chat_state_4", "chat_complete");
-    lu_mod.add_locale_from_value(
-        "MissionText_{}_completion_succeed_tip",
-        "completion_succeed_tip",
-    );
-    lu_mod.add_locale_from_value("MissionText_{}_in_progress", "in_progress");
-    lu_mod.add_locale_from_value("MissionText_{}_offer", "offer");
-    lu_mod.add_locale_from_value("MissionText_{}_ready_to_complete", "ready_to_complete");
+    lu_mod.add_locale(MISSION_NAME_PHRASE)?;
+    for (phrase_id, key) in MISSION_TEXT_PHRASES {
+        lu_mod.add_locale_from_value(phrase_id, key)?;
+    }
 
     // MissionText entry
     let mut mission_text_mod = Mod {
@@ -440,14 +644,26 @@ pub fn apply_mission_mod(mod_context: &mut ModContext, lu_mod: &mut Mod) -> eyre
         if let Some(target_group_string) = &task.target_group_string {
             task_mod.set_value("targetGroup", target_group_string.clone())?;
         } else {
-            let values = &task.group;
-            // join with commas
-            let group_string = values
-                .iter()
-                .map(|v| v.to_string())
-                .collect::<Vec<String>>()
-                .join(",");
-            task_mod.set_value("targetGroup", group_string)?;
+            // LOTs and mod ids, joined with commas
+            let mut parts = vec![];
+            for (position, target) in task.group.iter().enumerate() {
+                if position > 0 {
+                    parts.push(IdPart::Text(String::from(",")));
+                }
+                parts.push(match target {
+                    JsonValue::String(id) => IdPart::Id(id.clone()),
+                    JsonValue::Number(lot) if lot.is_i64() => IdPart::Text(lot.to_string()),
+                    _ => {
+                        return Err(ValueError::new(
+                            &format!("tasks.{}.group", index),
+                            target,
+                            "The targets of a task group need to be LOTs or ids of mods",
+                        )
+                        .into())
+                    }
+                });
+            }
+            task_mod.set_awaiting_ids("targetGroup", parts)?;
         }
 
         // Add icons
@@ -462,7 +678,7 @@ pub fn apply_mission_mod(mod_context: &mut ModContext, lu_mod: &mut Mod) -> eyre
 
         task_mod.set_fields(mod_context)?;
 
-        task_mod.add_locale("MissionTasks_{}_description");
+        task_mod.add_locale(MISSION_TASK_PHRASE)?;
 
         mod_context.mods.push(task_mod);
     }
@@ -470,6 +686,102 @@ pub fn apply_mission_mod(mod_context: &mut ModContext, lu_mod: &mut Mod) -> eyre
     Ok(())
 }
 
+/// Fill in the `reward_*` columns of a mission from its `rewards`. The columns
+/// are added to the values of the mod, so they aren't overwritten by defaults
+/// and can't be given twice.
+fn set_mission_rewards(lu_mod: &mut Mod, value: &JsonValue) -> eyre::Result<()> {
+    let rewards = serde_json::from_value::<MissionRewards>(value.clone())
+        .map_err(|error| ValueError::new("rewards", value, format!("Invalid rewards: {}", error)))
+        .suggestion(
+            "Rewards can have items, items_repeatable, emotes, currency, currency_repeatable, \
+            reputation and maxwallet.",
+        )?;
+
+    let mut columns = Vec::<(String, JsonValue)>::new();
+    for (items, item_column, count_column) in [
+        (&rewards.items, "reward_item{}", "reward_item{}_count"),
+        (
+            &rewards.items_repeatable,
+            "reward_item{}_repeatable",
+            "reward_item{}_repeat_count",
+        ),
+    ] {
+        if items.len() > 4 {
+            return Err(ValueError::new(
+                "rewards",
+                value,
+                format!("A mission can reward at most 4 items, not {}", items.len()),
+            ))
+            .suggestion("Give the other items in a package, or with another mission.");
+        }
+        for (index, reward) in items.iter().enumerate() {
+            let slot = (index + 1).to_string();
+            let (item, count) = reward.item_and_count();
+            columns.push((item_column.replace("{}", &slot), item.clone()));
+            columns.push((count_column.replace("{}", &slot), JsonValue::from(count)));
+        }
+    }
+    if rewards.emotes.len() > 4 {
+        return Err(ValueError::new(
+            "rewards",
+            value,
+            format!(
+                "A mission can reward at most 4 emotes, not {}",
+                rewards.emotes.len()
+            ),
+        ))
+        .suggestion("Give the other emotes with another mission.");
+    }
+    for (column, emote) in [
+        "reward_emote",
+        "reward_emote2",
+        "reward_emote3",
+        "reward_emote4",
+    ]
+    .iter()
+    .zip(&rewards.emotes)
+    {
+        columns.push((column.to_string(), emote.clone()));
+    }
+    for (column, amount) in [
+        ("reward_currency", rewards.currency),
+        ("reward_currency_repeatable", rewards.currency_repeatable),
+        ("reward_reputation", rewards.reputation),
+        ("reward_maxwallet", rewards.maxwallet),
+    ] {
+        if let Some(amount) = amount {
+            columns.push((column.to_string(), JsonValue::from(amount)));
+        }
+    }
+
+    for (column, column_value) in columns {
+        if lu_mod.values.contains_key(&column) {
+            return Err(ValueError::new(
+                &column,
+                &lu_mod.values[&column],
+                format!("{} is set by the rewards of the mission too", column),
+            ))
+            .suggestion(format!("Remove {} from the values of the mission.", column));
+        }
+        match &column_value {
+            JsonValue::String(id) => lu_mod.set_awaiting_id(&column, id)?,
+            JsonValue::Number(number) if number.is_i64() => {
+                lu_mod.set_value(&column, &column_value)?
+            }
+            _ => {
+                return Err(ValueError::new(
+                    "rewards",
+                    &column_value,
+                    format!("The {} reward needs to be a LOT or the id of a mod", column),
+                )
+                .into())
+            }
+        }
+        lu_mod.values.insert(column, column_value);
+    }
+    Ok(())
+}
+
 pub fn apply_npc_mod(mod_context: &mut ModContext, lu_mod: &mut Mod) -> eyre::Result<()> {
     lu_mod.set_default("render_asset", "animations\\\\minifig\\\\mf_ambient.kfm")?;
     lu_mod.set_default("animationGroupIDs", "93")?;
@@ -679,21 +991,127 @@ pub fn apply_enemy_mod(mod_context: &mut ModContext, lu_mod: &mut Mod) -> eyre::
     Ok(())
 }
 
+/// Add, change or remove a phrase of the locale by its id, e.g. to change the
+/// chat bubbles of a stock NPC.
+///
+/// `values.phrase` is the id of the phrase. With the action `add` the phrase
+/// is added with the translations in `locale`, with `edit` those translations
+/// replace the ones of an existing phrase. With `remove` the translations for
+/// the locales listed in `values.locales` are removed, or the whole phrase if
+/// there is no such list.
+pub fn apply_locale_mod(mod_context: &mut ModContext, lu_mod: &mut Mod) -> eyre::Result<()> {
+    let phrase_value = lu_mod
+        .values
+        .get("phrase")
+        .cloned()
+        .unwrap_or(JsonValue::Null);
+    let phrase_id = match phrase_value.as_str() {
+        Some(phrase_id) => phrase_id.to_string(),
+        None => {
+            return Err(ValueError::new(
+                "phrase",
+                &phrase_value,
+                "Locale mods need the id of a phrase as the value `phrase`",
+            )
+            .into())
+        }
+    };
+
+    let localization = &mut mod_context.localization;
+    let exists = localization.contains(&phrase_id);
+    match (lu_mod.action.as_str(), exists) {
+        ("add", false) => lu_mod.add_locale(&phrase_id)?,
+        ("add", true) => {
+            return Err(ValueError::new(
+                "phrase",
+                &phrase_value,
+                format!("Phrase {} already exists", phrase_id),
+            ))
+            .suggestion("Use the action edit to change the translations of an existing phrase.");
+        }
+        ("edit", false) | ("remove", false) => {
+            return Err(ValueError::new(
+                "phrase",
+                &phrase_value,
+                format!("Phrase {} is not in locale.xml", phrase_id),
+            ))
+            .suggestion("Use the action add to add a new phrase.");
+        }
+        ("edit", true) => {
+            // Included texts are read here, and kept in place of the
+            // `INCLUDE:` values for the checks of the translations
+            for (locale, value) in lu_mod.locale.clone() {
+                let text = lu_mod.locale_text(&locale, &value)?;
+                localization.change_phrase(
+                    &phrase_id,
+                    PhraseChange::Set(Translation {
+                        locale: locale.clone(),
+                        value: text.clone(),
+                    }),
+                );
+                lu_mod.locale.insert(locale, text);
+            }
+        }
+        ("remove", true) => match lu_mod.values.get("locales") {
+            None => localization.change_phrase(&phrase_id, PhraseChange::Remove),
+            Some(JsonValue::Array(locales)) => {
+                for locale in locales.iter().filter_map(JsonValue::as_str) {
+                    localization.change_phrase(
+                        &phrase_id,
+                        PhraseChange::RemoveTranslation(locale.to_string()),
+                    );
+                }
+            }
+            Some(other) => {
+                return Err(ValueError::new(
+                    "locales",
+                    other,
+                    "The locales to remove need to be a list",
+                )
+                .into())
+            }
+        },
+        (action, _) => {
+            return Err(ValueError::new(
+                "action",
+                &JsonValue::from(action),
+                format!("Unknown action {} for a locale mod", action),
+            ))
+            .suggestion("Locale mods support the actions add, edit and remove.");
+        }
+    }
+    Ok(())
+}
+
 pub fn apply_object_mod(mod_context: &mut ModContext, lu_mod: &mut Mod) -> eyre::Result<()> {
-    lu_mod.add_locale("Objects_{}_name");
+    lu_mod.add_locale(OBJECT_NAME_PHRASE)?;
 
     lu_mod.set_to_be_generated("id")?;
     lu_mod.set_fields(mod_context)
 }
 
 pub fn apply_component_mod(mod_context: &ModContext, lu_mod: &mut Mod) -> eyre::Result<()> {
+    if lu_mod.table.is_none()
+        && component_name_to_id(&lu_mod.mod_type).is_err()
+        && get_table(&mod_context.database, &lu_mod.get_target_table_name()).is_err()
+    {
+        let mod_type = JsonValue::from(lu_mod.mod_type.as_str());
+        let message = format!("Unknown mod type {}", lu_mod.mod_type);
+        return Err(eyre::Report::new(ValueError::new(
+            "type", &mod_type, message,
+        )))
+        .suggestion(
+            "Use one of the types item, sql, environmental, mission, npc, enemy or object, \
+            the name of a component, or the name of a table.",
+        );
+    }
     lu_mod.set_to_be_generated("id")?;
     lu_mod.set_fields(mod_context)
 }
 
 pub fn make_row_fields(
     table: &assembly_fdb::mem::Table,
-    values: &HashMap<String, OutputValue>,
+    values: &BTreeMap<String, OutputValue>,
 ) -> eyre::Result<Vec<OutputValue>> {
     let mut fields = Vec::with_capacity(table.column_count());
     for column in table.column_iter() {
@@ -706,31 +1124,42 @@ pub fn make_row_fields(
                     if json_value == &JsonValue::Null {
                         OutputValue::Known(Field::Nothing)
                     } else {
+                        let column_name = column.name();
                         let field = match value_type {
-                            ValueType::Boolean => {
-                                OutputValue::Known(Field::Boolean(json_value.as_bool().unwrap()))
-                            }
+                            ValueType::Boolean => OutputValue::Known(Field::Boolean(
+                                json_value.as_bool().ok_or_else(|| {
+                                    type_error(&column_name, json_value, "a boolean")
+                                })?,
+                            )),
                             ValueType::Integer => {
                                 if let Some(as_i64) = json_value.as_i64() {
                                     OutputValue::Known(Field::Integer(as_i64 as i32))
+                                } else if let Some(id) = json_value.as_str() {
+                                    OutputValue::AwaitingId(id.to_string())
                                 } else {
-                                    OutputValue::AwaitingId(
-                                        json_value.as_str().unwrap().to_string(),
-                                    )
+                                    return Err(type_error(
+                                        &column_name,
+                                        json_value,
+                                        "an integer or the id of a mod",
+                                    ));
                                 }
                             }
                             ValueType::BigInt => {
-                                OutputValue::Known(Field::BigInt(json_value.as_i64().unwrap()))
+                                OutputValue::Known(Field::BigInt(json_value.as_i64().ok_or_else(
+                                    || type_error(&column_name, json_value, "an integer"),
+                                )?))
                             }
                             ValueType::Float => OutputValue::Known(Field::Float(
-                                json_value.as_f64().unwrap() as f32,
-                            )),
-                            ValueType::Text => OutputValue::Known(Field::Text(
-                                json_value.as_str().unwrap().to_string(),
-                            )),
-                            ValueType::VarChar => OutputValue::Known(Field::Text(
-                                json_value.as_str().unwrap().to_string(),
+                                json_value.as_f64().ok_or_else(|| {
+                                    type_error(&column_name, json_value, "a number")
+                                })? as f32,
                             )),
+                            ValueType::Text => {
+                                OutputValue::Known(Field::Text(json_to_text(json_value)))
+                            }
+                            ValueType::VarChar => {
+                                OutputValue::Known(Field::Text(json_to_text(json_value)))
+                            }
                             ValueType::Nothing => OutputValue::Known(Field::Nothing),
                         };
                         field
@@ -747,6 +1176,15 @@ pub fn make_row_fields(
     Ok(fields)
 }
 
+fn type_error(column: &str, value: &JsonValue, expected: &str) -> eyre::Report {
+    ValueError::new(
+        column,
+        value,
+        format!("Expected {} for {}, but got {}", expected, column, value),
+    )
+    .into()
+}
+
 pub fn get_table<'a>(
     database: &'a assembly_fdb::mem::Database,
     name: &str,
@@ -760,6 +1198,59 @@ pub fn get_table<'a>(
     Err(eyre!("Table {} not found", name))
 }
 
+/// Like `get_table`, but suggests similarly named tables if the table doesn't exist.
+pub fn get_table_or_suggest<'a>(
+    database: &'a assembly_fdb::mem::Database,
+    name: &str,
+) -> eyre::Result<assembly_fdb::mem::Table<'a>> {
+    get_table(database, name).or_else(|error| {
+        let similar = similar_table_names(database, name)?;
+        let suggestion = if similar.is_empty() {
+            String::from("Check the spelling of the table name; table names are case sensitive.")
+        } else {
+            format!("Did you mean one of these tables? {}", similar.join(", "))
+        };
+        Err(error).suggestion(suggestion)
+    })
+}
+
+/// Names of tables in the database that are spelled like `name`.
+pub fn similar_table_names(
+    database: &assembly_fdb::mem::Database,
+    name: &str,
+) -> eyre::Result<Vec<String>> {
+    let name = name.to_lowercase();
+    let mut similar = Vec::new();
+    for table in database.tables()?.iter() {
+        let table_name = table?.name().into_owned();
+        let lowercase = table_name.to_lowercase();
+        let max_distance = std::cmp::max(2, name.len() / 3);
+        if lowercase.contains(&name)
+            || name.contains(&lowercase)
+            || edit_distance(&lowercase, &name) <= max_distance
+        {
+            similar.push(table_name);
+        }
+    }
+    similar.sort();
+    Ok(similar)
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 pub fn find_available_ids(
     table: &assembly_fdb::mem::Table,
     count: usize,
@@ -786,3 +1277,41 @@ pub fn find_available_ids(
     }
     Ok(available_ids)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_sources() {
+        let mut lu_mod = Mod::default();
+        lu_mod
+            .values
+            .insert(String::from("level"), JsonValue::from(3));
+        lu_mod.init_output_values();
+        lu_mod.set_default("level", 1).unwrap();
+        lu_mod.set_default("stackSize", 1).unwrap();
+        lu_mod.set_default("localize", false).unwrap();
+        lu_mod.set_value("localize", true).unwrap();
+        lu_mod.set_to_be_generated("id").unwrap();
+        lu_mod.set_awaiting_id("skillID", "pirates:slash").unwrap();
+
+        assert_eq!(lu_mod.value_source("level"), Some(ValueSource::Mod));
+        assert_eq!(lu_mod.value_source("stackSize"), Some(ValueSource::Default));
+        assert_eq!(lu_mod.value_source("localize"), Some(ValueSource::Fixed));
+        assert_eq!(lu_mod.value_source("id"), Some(ValueSource::Generated));
+        assert_eq!(lu_mod.value_source("skillID"), Some(ValueSource::Awaited));
+        assert_eq!(lu_mod.value_source("name"), None);
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("objects", "objects"), 0);
+        assert_eq!(edit_distance("objects", ""), 7);
+        assert_eq!(edit_distance("objcts", "objects"), 1);
+        assert_eq!(edit_distance("itemcomponent", "itemcomponnet"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("épée", "epee"), 2);
+    }
+}
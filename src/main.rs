@@ -1,19 +1,39 @@
+mod build_cache;
+mod changelog;
 mod component;
+mod diagnostics;
+mod json_edit;
 mod locale;
 mod lu_mod;
 mod manifest;
+mod migration;
 mod mission;
 mod mod_context;
+mod mod_file;
 mod mods;
+mod report;
+mod script;
+mod translation;
+mod watch;
+use crate::build_cache::{hash_files, BuildCache};
+use crate::changelog::write_changelog;
 use crate::component::{component_name_to_id, mod_type_to_table_name};
 use crate::locale::Localization;
 use crate::locale::Phrase;
 use crate::locale::Translation;
 use crate::lu_mod::*;
 use crate::manifest::Manifest;
+use crate::migration::{
+    check_version, migrate_lookup_ids, migrate_mods, read_lookup, write_mods_version, VersionCheck,
+    FORMAT_VERSION,
+};
 use crate::mod_context::LookupFile;
 use crate::mod_context::ModContext;
-use crate::mods::Mods;
+use crate::mod_file::{read_mod_file, ModFile};
+use crate::mods::{Mods, Profile};
+use crate::report::{AllocatedId, BuildReport, PackReport};
+use crate::translation::{export_translations, import_translations};
+use crate::watch::watch;
 use assembly_fdb::{core::Field, mem::Database, store};
 use color_eyre::{
     eyre::{self, eyre, WrapErr},
@@ -21,11 +41,16 @@ use color_eyre::{
 };
 use mapr::Mmap;
 use rusqlite::{params_from_iter, Connection};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::{fmt::Write, fs::File, io::BufWriter, io::Write as _, time::Instant};
 use structopt::StructOpt;
 
+/// Where the output database is written, relative to mods.json.
+const OUTPUT_FDB_PATH: &str = "../res/cdclient.fdb";
+/// Where the output locale is written, relative to mods.json.
+const OUTPUT_LOCALE_PATH: &str = "../locale/locale.xml";
+
 #[derive(StructOpt)]
 #[structopt(author = "zaop")]
 #[structopt(
@@ -70,15 +95,98 @@ struct Options {
         default_value = "output.json"
     )]
     output: PathBuf,
+
+    #[structopt(
+        long = "show-defaults",
+        help = "Print the effective row of every mod, including values filled in by defaults."
+    )]
+    show_defaults: bool,
+
+    #[structopt(
+        short = "p",
+        long = "profile",
+        help = "The profile from mods.json that selects which packs are applied."
+    )]
+    profile: Option<String>,
+
+    #[structopt(
+        long = "report",
+        help = "Write a JSON summary of the build to this file, for use by other tools."
+    )]
+    report: Option<PathBuf>,
+
+    #[structopt(
+        long = "force",
+        help = "Rebuild all outputs, even if their inputs haven't changed since the last build."
+    )]
+    force: bool,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Build, then rebuild whenever mods.json, a pack or a file included by a
+    /// mod changes.
+    Watch {
+        #[structopt(
+            long = "validate",
+            help = "Only apply the mods to check them for errors, without writing any output."
+        )]
+        validate: bool,
+    },
+    /// Export the texts of the mods for translators, or import translations.
+    Locale(LocaleCommand),
+}
+
+#[derive(StructOpt)]
+enum LocaleCommand {
+    /// Write the texts of all mods to a CSV or XLIFF file, with a column or a
+    /// file per locale.
+    Export {
+        #[structopt(help = "The .csv, .xlf or .xliff file to write.")]
+        file: PathBuf,
+    },
+    /// Merge the translations in a CSV or XLIFF file written by export into
+    /// the mod files.
+    Import {
+        #[structopt(help = "The .csv, .xlf or .xliff file to read.")]
+        file: PathBuf,
+    },
 }
 
 fn main() -> eyre::Result<()> {
     color_eyre::config::HookBuilder::default()
         .display_env_section(false)
         .install()?;
-    let opts = Options::from_args();
+    let mut opts = Options::from_args();
+
+    // The build changes the working directory to the one of mods.json, so
+    // paths given relative to where the tool was started are resolved first
+    let current_dir = std::env::current_dir()?;
+    opts.report = opts.report.map(|path| current_dir.join(path));
+
+    match &opts.command {
+        None => build(&opts, false).map(|_| ()),
+        Some(Command::Watch { validate }) => {
+            opts.input = current_dir.join(&opts.input);
+            watch(&opts, *validate)
+        }
+        Some(Command::Locale(LocaleCommand::Export { file })) => {
+            export_translations(&opts, &current_dir.join(file))
+        }
+        Some(Command::Locale(LocaleCommand::Import { file })) => {
+            import_translations(&opts, &current_dir.join(file))
+        }
+    }
+}
 
+/// Run the whole pipeline, or with `validate_only` only apply the mods and
+/// resolve their IDs. Returns the files included by mods, for watch mode.
+fn build(opts: &Options, validate_only: bool) -> eyre::Result<Vec<PathBuf>> {
     let start_time = Instant::now();
+    let mut report = BuildReport::default();
 
     // verify opts.input exists
     if !&opts.input.is_file() {
@@ -90,7 +198,7 @@ fn main() -> eyre::Result<()> {
 
     println!("Using configuration file: {}", opts.input.display());
 
-    let configuration = read_or_create_json::<Mods>(&PathBuf::from(&opts.input))
+    let mut configuration = read_or_create_json::<Mods>(&PathBuf::from(&opts.input))
         .wrap_err("Failed to read mods.json")
         .suggestion(
             "Ensure that mods.json hasn't been corrupted and contains valid JSON. \
@@ -98,16 +206,79 @@ fn main() -> eyre::Result<()> {
             custom mod priorities if you configured those.",
         )?;
 
+    match migrate_mods(&mut configuration)? {
+        VersionCheck::UpToDate => {}
+        VersionCheck::Older(_) => {
+            write_mods_version(&opts.input, &configuration.version)
+                .wrap_err("Failed to update mods.json")?;
+        }
+        VersionCheck::Newer(version) => report.warn(format!(
+            "mods.json has version {}, but this version of lu-mod-tool only supports up to \
+            version {}. Settings it doesn't know about are ignored, and mods.json won't be updated.",
+            version, FORMAT_VERSION
+        )),
+    }
+
+    let report_path = opts.report.clone();
+
     // Default to current working dir if this fails
     let _ = std::env::set_current_dir(opts.input.parent().unwrap());
 
+    let profile = selected_profile(&configuration, opts)?;
+
+    let packs = find_packs(&configuration, profile.as_ref())?;
+
+    // Skip the outputs whose inputs haven't changed since the last build
+    let mods_json_path = PathBuf::from(opts.input.file_name().unwrap());
+    let lookup_path = Path::new("lookup.json");
+    let locale_source_path = Path::new("locale.xml");
+    let cache_path = Path::new("build-cache.json");
+    let mut inputs = vec![
+        mods_json_path,
+        configuration.database.clone(),
+        lookup_path.to_path_buf(),
+    ];
+    for mods_dir in &packs.dirs {
+        inputs.push(mods_dir.join("manifest.json"));
+    }
+    for (manifest_path, manifest) in &packs.enabled {
+        inputs.extend(manifest.mod_files(manifest_path.parent().unwrap())?);
+    }
+    let cache = BuildCache::read(cache_path);
+    // --show-defaults prints the rows of the mods, so they have to be applied
+    let inputs_unchanged =
+        !opts.force && !validate_only && !opts.show_defaults && cache.inputs_unchanged(&inputs);
+    let database_up_to_date = inputs_unchanged
+        && cache.database_inputs_unchanged()
+        && configuration.sqlite.is_file()
+        && Path::new(OUTPUT_FDB_PATH).is_file();
+    let locale_up_to_date = inputs_unchanged
+        && cache.locale_inputs_unchanged()
+        && Path::new(OUTPUT_LOCALE_PATH).is_file();
+    if database_up_to_date && locale_up_to_date {
+        println!("Nothing changed since the last build. Use --force to rebuild anyway.");
+        report.up_to_date = true;
+        if let Some(report_path) = report_path {
+            write_json_pretty(&report, &report_path).wrap_err_with(|| {
+                format!("Failed to write build report to {}", report_path.display())
+            })?;
+        }
+        let root = std::env::current_dir()?;
+        return Ok(cache
+            .database_inputs
+            .keys()
+            .chain(cache.locale_inputs.keys())
+            .map(|path| root.join(path))
+            .collect());
+    }
+
     let timer = Instant::now();
 
     // Load base FDB
     print!("Opening database... ");
     std::io::stdout().flush()?;
     let database_source_path = &configuration.database;
-    let database_destination_path = "../res/cdclient.fdb";
+    let database_destination_path = OUTPUT_FDB_PATH;
     if !database_source_path.is_file() {
         std::fs::copy(database_destination_path, database_source_path)
             .wrap_err("Failed to copy cdclient.fdb to the mods folder.")
@@ -119,31 +290,71 @@ fn main() -> eyre::Result<()> {
     let mmap = unsafe { Mmap::map(&src_file)? };
     let buffer: &[u8] = &mmap;
 
-    let timer = print_timer(timer);
+    let timer = report.finish_stage("Opening database", timer);
 
     // Load base locale
     print!("Reading locale... ");
     std::io::stdout().flush()?;
 
-    let locale_source_path = Path::new("locale.xml");
-    let locale_destination_path = Path::new("../locale/locale.xml");
+    let locale_destination_path = Path::new(OUTPUT_LOCALE_PATH);
     if !locale_source_path.is_file() {
         std::fs::copy(locale_destination_path, locale_source_path)?;
     }
-    let localization = read_xml::<Localization>(locale_source_path)?;
+    let localization = Localization::read(locale_source_path)?;
 
-    let timer = print_timer(timer);
+    let timer = report.finish_stage("Reading locale", timer);
 
     // Load lookup
-    let lookup_path = Path::new("lookup.json");
-    let lookup = read_or_create_json::<LookupFile>(lookup_path)
+    let lookup_file = read_lookup(lookup_path)
         .wrap_err("Couldn't parse lookup.json")
         .suggestion(
             "Ensure that lookup.json hasn't been corrupted and contains valid JSON. \
             If you don't need to preserve previously generated IDs, you can delete the \
             file to start over.",
-        )?
-        .ids;
+        )?;
+    let lookup_version = check_version("lookup.json", &lookup_file.version)?;
+    if let VersionCheck::Newer(version) = lookup_version {
+        report.warn(format!(
+            "lookup.json has version {}, but this version of lu-mod-tool only supports up to \
+            version {}. It will be written back as version {}.",
+            version, FORMAT_VERSION, FORMAT_VERSION
+        ));
+    }
+    let mut lookup = lookup_file.ids;
+
+    // Carry over IDs from lookup.json files written before mod ids were
+    // prefixed with the name of their pack. The mods of disabled packs are
+    // migrated too, so that their IDs are kept when they're enabled again.
+    let lookup_needs_migration = matches!(lookup_version, VersionCheck::Older(_));
+    if lookup_needs_migration {
+        println!(
+            "Migrating lookup.json to version {}: prefixing mod ids with pack names",
+            FORMAT_VERSION
+        );
+        let mut mod_ids = Vec::new();
+        for (manifest_path, manifest) in packs.enabled.iter().chain(&packs.disabled) {
+            for file in manifest.mod_files(manifest_path.parent().unwrap())? {
+                match read_mod_file(&file) {
+                    Ok(mod_file) => {
+                        mod_ids.extend(mod_file.mods.iter().map(|(lu_mod, _)| {
+                            qualify_id(&packs.names, &manifest.name, &lu_mod.id)
+                        }))
+                    }
+                    Err(error) => report.warn(format!(
+                        "Couldn't read {} to migrate lookup.json, the IDs of its mods \
+                        will be migrated in a later build: {}",
+                        file.display(),
+                        error
+                    )),
+                }
+            }
+        }
+        migrate_lookup_ids(
+            &mut lookup,
+            &packs.names,
+            mod_ids.iter().map(String::as_str),
+        );
+    }
 
     // Set up ModContext
     let mut mod_context = ModContext::<'_> {
@@ -155,32 +366,41 @@ fn main() -> eyre::Result<()> {
         mods: Default::default(),
         server_sql: Default::default(),
         lookup,
+        packs: packs.names,
     };
 
-    // TODO check version
-
-    // TODO update mods.json if changed
-
     // TODO handle Copy Object.
 
     // TODO priorities.
 
     println!("Applying mods.");
-    // Find all directories with a manifest.json file.
-    let mut mods_dirs = Vec::new();
-    for entry in std::fs::read_dir(".")? {
-        let path = entry?.path();
-        if path.is_dir() && path.join("manifest.json").is_file() {
-            mods_dirs.push(path);
-        }
+    // Loop over packs.
+    for (manifest_path, manifest) in &packs.enabled {
+        let pack_report = apply_manifest(&mut mod_context, manifest_path, manifest)?;
+        report.packs.push(pack_report);
     }
-    // Loop over dirs.
-    for mods_dir in mods_dirs {
-        // Apply manifest.
-        let manifest_path = &mods_dir.join("manifest.json");
-        apply_manifest(&mut mod_context, manifest_path)?;
+
+    // Mods created while applying others, like mission tasks, are only known now
+    let lookup_migrated = !lookup_needs_migration
+        || migrate_lookup_ids(
+            &mut mod_context.lookup,
+            &mod_context.packs,
+            mod_context.mods.iter().map(|lu_mod| lu_mod.id.as_str()),
+        );
+    if !lookup_migrated {
+        report.warn(format!(
+            "Some mod ids in lookup.json couldn't be matched to a mod and keep their old \
+            format, so lookup.json stays at version {}.",
+            match lookup_version {
+                VersionCheck::Older(version) => version,
+                _ => FORMAT_VERSION,
+            }
+        ));
     }
 
+    // The changelog compares against the IDs from before this build
+    let previous_lookup = mod_context.lookup.clone();
+
     // Count number of IDs that should be generated for each table
     let mut new_ids_needed: HashMap<String, usize> = HashMap::new();
 
@@ -236,6 +456,11 @@ fn main() -> eyre::Result<()> {
                 // Check if ID is already in lookup.json
                 if let Some(id) = mod_context.lookup.get(&lu_mod.id) {
                     *field = OutputValue::Known(Field::Integer(*id));
+                    report.ids.reused.push(AllocatedId {
+                        mod_id: lu_mod.id.clone(),
+                        table: table_name.clone(),
+                        id: *id,
+                    });
                 }
                 // Otherwise, take one of the generated ones
                 else if let Some(ids) = available_ids.get_mut(&table_name) {
@@ -248,6 +473,11 @@ fn main() -> eyre::Result<()> {
                     });
                     *field = OutputValue::Known(Field::Integer(id));
                     mod_context.lookup.insert(lu_mod.id.clone(), id);
+                    report.ids.new.push(AllocatedId {
+                        mod_id: lu_mod.id.clone(),
+                        table: table_name.clone(),
+                        id,
+                    });
                 } else {
                     return Err(eyre!(
                         "A database ID for table `{}` was requested, but none were generated. \
@@ -262,33 +492,180 @@ fn main() -> eyre::Result<()> {
     // Fill in AwaitingIDs
     for lu_mod in &mut mod_context.mods {
         for field in lu_mod.fields.iter_mut() {
-            if let OutputValue::AwaitingId(id) = field {
-                if let Some(id) = mod_context.lookup.get(id) {
-                    *field = OutputValue::Known(Field::Integer(*id));
-                } else {
-                    return Err(eyre!(
-                        "Mod {} references an object with ID {}, but no database ID was generated for this.",
-                        lu_mod.id,
-                        id
-                    ))
-                    .suggestion(format!(
-                        "Either remove the reference to {} from your mods, or add a mod with that as ID.",
-                        id
-                    ));
+            match field {
+                OutputValue::AwaitingId(id) => {
+                    let id = lookup_reference(
+                        &mod_context.packs,
+                        &mod_context.lookup,
+                        &lu_mod.pack,
+                        &lu_mod.id,
+                        id,
+                    )?;
+                    *field = OutputValue::Known(Field::Integer(id));
+                }
+                OutputValue::AwaitingIds(parts) => {
+                    let mut text = String::new();
+                    for part in parts.iter() {
+                        match part {
+                            IdPart::Text(part) => text.push_str(part),
+                            IdPart::Id(id) => {
+                                let id = lookup_reference(
+                                    &mod_context.packs,
+                                    &mod_context.lookup,
+                                    &lu_mod.pack,
+                                    &lu_mod.id,
+                                    id,
+                                )?;
+                                text.push_str(&id.to_string());
+                            }
+                        }
+                    }
+                    *field = OutputValue::Known(Field::Text(text));
                 }
+                _ => {}
             }
         }
     }
 
-    // Add locale
+    if opts.show_defaults
+        || mod_context
+            .mods
+            .iter()
+            .any(|m| m.show_defaults == Some(true))
+    {
+        print_effective_rows(&mod_context, opts.show_defaults)?;
+    }
+
+    let root = std::env::current_dir()?;
+    let includes = mod_context
+        .mods
+        .iter()
+        .flat_map(|lu_mod| lu_mod.includes.iter().chain(&lu_mod.locale_includes))
+        .map(|path| root.join(path))
+        .collect::<Vec<PathBuf>>();
+    if validate_only {
+        print!("Validated mods in ");
+        let _ = report.finish_stage("Validating mods", timer);
+        return Ok(includes);
+    }
+
+    // Add locale, filling in the locales mods don't have a translation for.
+    // The client picks one of the phrases with the same id unpredictably, so
+    // phrases that already exist are an error. Maps the ids of phrases added
+    // by mods to the mod that added them.
+    let mut phrase_sources = HashMap::<String, String>::new();
+    let fallback_locale = &mod_context.configuration.fallback_locale;
     for lu_mod in &mod_context.mods {
+        let mut filled_locales = Vec::<String>::new();
         for new_locale_entry in &lu_mod.new_locale_entries {
             let mut entry = new_locale_entry.clone();
-            entry.id = new_locale_entry.id.replace(
-                "{}",
-                &mod_context.lookup.get(&lu_mod.id).unwrap().to_string(),
-            );
-            mod_context.localization.phrases.phrase.push(entry);
+            if entry.id.contains("{}") {
+                entry.id = new_locale_entry.id.replace(
+                    "{}",
+                    &mod_context.lookup.get(&lu_mod.id).unwrap().to_string(),
+                );
+            }
+            let source = phrase_sources.get(&entry.id);
+            if source.is_some() || mod_context.localization.contains(&entry.id) {
+                let error = eyre!(
+                    "Mod {} adds phrase {}, which already exists",
+                    lu_mod.id,
+                    entry.id
+                );
+                return Err(match source {
+                    Some(other_mod) => error.suggestion(format!(
+                        "Mod {} adds this phrase too. If a mission gives the same text under \
+                        two names, like chat_accept and accept_chat_bubble, remove one of them.",
+                        other_mod
+                    )),
+                    None => error.suggestion(
+                        "To change a phrase of the client, use a mod of type locale with the action edit.",
+                    ),
+                });
+            }
+            phrase_sources.insert(entry.id.clone(), lu_mod.id.clone());
+            for problem in entry.translation_problems(fallback_locale) {
+                report.warn(format!(
+                    "Phrase {} of mod {}: {}",
+                    entry.id, lu_mod.id, problem
+                ));
+            }
+            // Without a fallback text there's nothing to fill in with
+            if !entry
+                .translations
+                .iter()
+                .any(|t| &t.locale == fallback_locale)
+            {
+                let missing = mod_context
+                    .localization
+                    .locales
+                    .iter()
+                    .filter(|locale| !entry.translations.iter().any(|t| &t.locale == *locale))
+                    .count();
+                report.warn(format!(
+                    "Phrase {} of mod {} has no {} text, {} locales are left without a translation",
+                    entry.id, lu_mod.id, fallback_locale, missing
+                ));
+            }
+            for locale in
+                entry.fill_missing_locales(&mod_context.localization.locales, fallback_locale)
+            {
+                if !filled_locales.contains(&locale) {
+                    filled_locales.push(locale);
+                }
+            }
+            mod_context.localization.add_phrase(entry);
+            report.locale_phrases_added += 1;
+        }
+        if !filled_locales.is_empty() {
+            report.warn(format!(
+                "Mod {} has no translation for {}, using the {} text",
+                lu_mod.id,
+                filled_locales.join(", "),
+                fallback_locale
+            ));
+        }
+    }
+
+    // Check the texts of locale mods that edit phrases of the client against
+    // the text they replace. Applying the mods replaced `INCLUDE:` values in
+    // their `locale` with the included texts.
+    let edited_phrases = mod_context
+        .mods
+        .iter()
+        .filter(|lu_mod| lu_mod.mod_type == "locale" && lu_mod.action == "edit")
+        .filter_map(|lu_mod| Some((lu_mod, lu_mod.values.get("phrase")?.as_str()?)))
+        .collect::<Vec<_>>();
+    if !edited_phrases.is_empty() {
+        let phrase_ids = edited_phrases
+            .iter()
+            .map(|(_, phrase_id)| phrase_id.to_string())
+            .collect::<HashSet<String>>();
+        let source_translations = mod_context.localization.source_translations(&phrase_ids)?;
+        for (lu_mod, phrase_id) in edited_phrases {
+            let mut phrase = Phrase {
+                id: phrase_id.to_string(),
+                translations: lu_mod
+                    .locale
+                    .iter()
+                    .map(|(locale, value)| Translation {
+                        locale: locale.clone(),
+                        value: value.clone(),
+                    })
+                    .collect(),
+            };
+            if !lu_mod.locale.contains_key(fallback_locale) {
+                let source = source_translations.get(phrase_id).and_then(|translations| {
+                    translations.iter().find(|t| &t.locale == fallback_locale)
+                });
+                phrase.translations.extend(source.cloned());
+            }
+            for problem in phrase.translation_problems(fallback_locale) {
+                report.warn(format!(
+                    "Phrase {} of mod {}: {}",
+                    phrase_id, lu_mod.id, problem
+                ));
+            }
         }
     }
 
@@ -329,8 +706,119 @@ fn main() -> eyre::Result<()> {
     }
 
     print!("Applied mods in ");
-    let timer = print_timer(timer);
+    let timer = report.finish_stage("Applying mods", timer);
+
+    let timer = if database_up_to_date {
+        println!("Output database is up to date, skipping.");
+        report.skipped_stages.extend(
+            [
+                "Building output database",
+                "Applying SQL mods",
+                "Exporting SQLite",
+                "Exporting FDB",
+            ]
+            .map(String::from),
+        );
+        timer
+    } else {
+        export_database(&mod_context, &component_registry, &mut report, timer)?
+    };
+
+    if locale_up_to_date {
+        println!("Output locale is up to date, skipping.");
+        report.skipped_stages.push(String::from("Exporting locale"));
+    } else {
+        print!("Exporting locale... ");
+        std::io::stdout().flush()?;
+
+        mod_context
+            .localization
+            .write(Path::new(OUTPUT_LOCALE_PATH))?;
+    }
+    let timer = report.finish_stage("Exporting locale", timer);
+
+    print!("Exporting lookup.json... ");
+    std::io::stdout().flush()?;
+    let lookup_save = LookupFile {
+        // Stay at the old version until every id has been migrated
+        version: if lookup_migrated {
+            FORMAT_VERSION.to_string()
+        } else {
+            lookup_file.version.clone()
+        },
+        ids: mod_context.lookup.clone(),
+    };
+    write_json(lookup_save, lookup_path)?;
+    let timer = report.finish_stage("Exporting lookup.json", timer);
+
+    print!("Writing changelog... ");
+    std::io::stdout().flush()?;
+    let changelog_path = mod_context.configuration.changelog.clone();
+    write_changelog(
+        &mod_context,
+        &previous_lookup,
+        cache.mods.as_ref(),
+        &changelog_path,
+    )
+    .wrap_err_with(|| format!("Failed to write changelog to {}", changelog_path.display()))?;
+    let _ = report.finish_stage("Writing changelog", timer);
+
+    // Remember what this build was made from. lookup.json is hashed as
+    // written, so that the IDs generated in this build don't cause a rebuild.
+    let database_inputs = mod_context
+        .mods
+        .iter()
+        .flat_map(|lu_mod| lu_mod.includes.iter().cloned())
+        .collect::<Vec<PathBuf>>();
+    let mut locale_inputs = vec![locale_source_path.to_path_buf()];
+    for lu_mod in &mod_context.mods {
+        locale_inputs.extend(lu_mod.locale_includes.iter().cloned());
+    }
+    let cache = BuildCache {
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        inputs: hash_files(&inputs)?,
+        database_inputs: hash_files(&database_inputs)?,
+        locale_inputs: hash_files(&locale_inputs)?,
+        mods: Some(
+            mod_context
+                .mods
+                .iter()
+                .map(|lu_mod| lu_mod.id.clone())
+                .collect(),
+        ),
+    };
+    write_json_pretty(&cache, cache_path).wrap_err("Failed to write build-cache.json")?;
+
+    println!("\nGenerated IDs:");
+    for (key, id) in &mod_context.lookup {
+        println!(" {:>5} : {}", id, key);
+    }
+
+    let duration = start_time.elapsed();
+    println!(
+        "\nFinished in {}.{:#03}s",
+        duration.as_secs(),
+        duration.subsec_millis()
+    );
 
+    if let Some(report_path) = report_path {
+        report.total_ms = duration.as_millis();
+        write_json_pretty(&report, &report_path).wrap_err_with(|| {
+            format!("Failed to write build report to {}", report_path.display())
+        })?;
+    }
+
+    Ok(includes)
+}
+
+/// Build the output SQLite database and FDB from the source FDB, the rows of
+/// the mods and the SQL mods.
+fn export_database(
+    mod_context: &ModContext,
+    component_registry: &[Vec<Field>],
+    report: &mut BuildReport,
+    timer: Instant,
+) -> eyre::Result<Instant> {
     // Create destination database and merge new rows into it
     print!("Building output database... ");
     std::io::stdout().flush()?;
@@ -350,8 +838,8 @@ fn main() -> eyre::Result<()> {
 
         let to_add = {
             match src_table.name().into_owned().as_str() {
-                "ComponentsRegistry" => component_registry.clone(),
-                _ => get_rows_for_insertion(&mod_context, src_table.name().into_owned().as_str()),
+                "ComponentsRegistry" => component_registry.to_vec(),
+                _ => get_rows_for_insertion(mod_context, src_table.name().into_owned().as_str()),
             }
         };
 
@@ -387,6 +875,10 @@ fn main() -> eyre::Result<()> {
             if addable.is_empty() {
                 continue;
             }
+            *report
+                .rows_added
+                .entry(src_table.name().into_owned())
+                .or_insert(0) += 1;
             // sqlite
             insert_statement.execute(params_from_iter(addable.iter()))?;
             // fdb
@@ -417,7 +909,7 @@ fn main() -> eyre::Result<()> {
         dest_fdb.push_table(src_table.name_raw(), dest_table);
     }
 
-    let timer = print_timer(timer);
+    let timer = report.finish_stage("Building output database", timer);
 
     print!("Applying SQL mods... ");
     std::io::stdout().flush()?;
@@ -432,98 +924,327 @@ fn main() -> eyre::Result<()> {
             dest_sqlite.execute(sql_str.as_str(), rusqlite::params![])?;
         }
     }
-    let timer = print_timer(timer);
+    let timer = report.finish_stage("Applying SQL mods", timer);
 
     print!("Exporting SQLite... ");
     std::io::stdout().flush()?;
     dest_sqlite.execute("COMMIT", rusqlite::params![])?;
 
-    let timer = print_timer(timer);
+    let timer = report.finish_stage("Exporting SQLite", timer);
 
     print!("Exporting FDB... ");
     std::io::stdout().flush()?;
-    let fdb_path = Path::new("../res/cdclient.fdb");
+    let fdb_path = Path::new(OUTPUT_FDB_PATH);
     let dest_file = File::create(fdb_path)?;
     let mut dest_out = BufWriter::new(dest_file);
     dest_fdb
         .write(&mut dest_out)
         .wrap_err("Failed to write output database")?;
 
-    let timer = print_timer(timer);
-
-    print!("Exporting locale... ");
-    std::io::stdout().flush()?;
+    Ok(report.finish_stage("Exporting FDB", timer))
+}
 
-    mod_context.localization.locales.count = mod_context.localization.locales.locale.len();
-    mod_context.localization.phrases.count = mod_context.localization.phrases.phrase.len();
-    write_xml(&mod_context.localization, Path::new("../locale/locale.xml"))?;
-    let timer = print_timer(timer);
+/// The packs in the directory of mods.json.
+struct Packs {
+    /// Directories of all packs, including disabled ones.
+    dirs: Vec<PathBuf>,
+    /// Names of all packs, which are the namespaces of mod ids.
+    names: Vec<String>,
+    /// Paths and contents of the manifests of the enabled packs.
+    enabled: Vec<(PathBuf, Manifest)>,
+    /// Paths and contents of the manifests of the disabled packs.
+    disabled: Vec<(PathBuf, Manifest)>,
+}
 
-    print!("Exporting lookup.json... ");
-    std::io::stdout().flush()?;
-    let lookup_save = LookupFile {
-        ids: mod_context.lookup.clone(),
-    };
-    write_json(lookup_save, Path::new("lookup.json"))?;
-    let _ = print_timer(timer);
+/// Find and read the manifests of all packs, checking that their names are
+/// unique. Must be called from the directory of mods.json.
+fn find_packs(configuration: &Mods, profile: Option<&Profile>) -> eyre::Result<Packs> {
+    // Find all directories with a manifest.json file.
+    let mut dirs = Vec::new();
+    find_mods_dirs(Path::new("."), &mut dirs)?;
+    // Read all manifests first, so that mod ids can refer to any pack.
+    let mut names = Vec::new();
+    let mut enabled = Vec::new();
+    let mut disabled = Vec::new();
+    for mods_dir in &dirs {
+        let manifest_path = mods_dir.join("manifest.json");
+        let manifest = read_json::<Manifest>(&manifest_path)
+            .wrap_err_with(|| format!("Failed to read {}", manifest_path.display()))?;
+        if manifest.name.contains(':') {
+            return Err(eyre!(
+                "Pack name {} in {} contains a colon",
+                manifest.name,
+                manifest_path.display()
+            ))
+            .suggestion("Colons separate pack names from mod ids, rename the pack.");
+        }
+        if names.contains(&manifest.name) {
+            return Err(eyre!(
+                "There are multiple packs named {}, the second one is in {}",
+                manifest.name,
+                manifest_path.display()
+            ))
+            .suggestion("Pack names have to be unique, rename one of the packs.");
+        }
+        names.push(manifest.name.clone());
 
-    println!("\nGenerated IDs:");
-    let mut keys = mod_context.lookup.keys().collect::<Vec<&String>>();
-    keys.sort();
-    for key in keys {
-        println!(" {:>5} : {}", mod_context.lookup[key], key);
+        let directory = pack_directory_key(mods_dir);
+        if !configuration.is_pack_enabled(&directory, &manifest.name, profile) {
+            println!("Skipping {} (disabled)", manifest.name);
+            disabled.push((manifest_path, manifest));
+            continue;
+        }
+        enabled.push((manifest_path, manifest));
     }
-
-    let duration = start_time.elapsed();
-    println!(
-        "\nFinished in {}.{:#03}s",
-        duration.as_secs(),
-        duration.subsec_millis()
-    );
-
-    Ok(())
+    Ok(Packs {
+        dirs,
+        names,
+        enabled,
+        disabled,
+    })
 }
 
-fn apply_manifest(mod_context: &mut ModContext, manifest_path: &Path) -> eyre::Result<()> {
-    let manifest = read_json::<Manifest>(manifest_path)?;
-
+fn apply_manifest(
+    mod_context: &mut ModContext,
+    manifest_path: &Path,
+    manifest: &Manifest,
+) -> eyre::Result<PackReport> {
     println!("Applying {}", &manifest.name);
-    for mod_file in &manifest.files {
-        let real_path = &manifest_path.parent().unwrap().join(mod_file);
+    let pack_dir = manifest_path.parent().unwrap();
+    let first_mod = mod_context.mods.len();
+    let files = manifest.mod_files(pack_dir)?;
+    for real_path in &files {
         println!("  └ {:?}", &real_path);
-        apply_mod_file(mod_context, &manifest, real_path)?;
+        apply_mod_file(mod_context, manifest, real_path)?;
+    }
+    Ok(PackReport {
+        name: manifest.name.clone(),
+        directory: pack_directory_key(pack_dir),
+        files,
+        mods: mod_context.mods.len() - first_mod,
+    })
+}
+
+/// Recursively find all directories with a manifest.json file, in alphabetical
+/// order. Directories of packs themselves are not searched for nested packs.
+fn find_mods_dirs(dir: &Path, mods_dirs: &mut Vec<PathBuf>) -> eyre::Result<()> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+    paths.sort();
+    for path in paths {
+        let hidden =
+            matches!(path.file_name(), Some(name) if name.to_string_lossy().starts_with('.'));
+        if !path.is_dir() || hidden {
+            continue;
+        }
+        if path.join("manifest.json").is_file() {
+            mods_dirs.push(path);
+        } else {
+            find_mods_dirs(&path, mods_dirs)?;
+        }
     }
     Ok(())
 }
 
 fn apply_mod_file(
     mod_context: &mut ModContext,
-    _manifest: &Manifest,
+    manifest: &Manifest,
     file: &Path,
 ) -> eyre::Result<()> {
-    let mods: Vec<Mod> = read_json::<Vec<Mod>>(file)?;
+    let ModFile { source, mods } = read_mod_file(file)?;
     let dir = file.parent().unwrap();
-    for mut lu_mod in mods {
+    for (index, (lu_mod, span)) in mods.into_iter().enumerate() {
         println!("    └ {:?}", &lu_mod.id);
-        lu_mod.dir = dir.into();
-        lu_mod.init_output_values();
-
-        let _ = match lu_mod.mod_type.as_str() {
-            "item" => apply_item_mod(mod_context, &mut lu_mod)?,
-            "sql" => apply_sql_mod(mod_context, &mut lu_mod)?,
-            "environmental" => apply_environmental_mod(mod_context, &mut lu_mod)?,
-            "mission" => apply_mission_mod(mod_context, &mut lu_mod)?,
-            "npc" => apply_npc_mod(mod_context, &mut lu_mod)?,
-            "enemy" => apply_enemy_mod(mod_context, &mut lu_mod)?,
-            "object" => apply_object_mod(mod_context, &mut lu_mod)?,
-            _ => apply_component_mod(mod_context, &mut lu_mod)?,
-        };
+        let id = lu_mod.id.clone();
+        apply_mod(mod_context, manifest, dir, lu_mod)
+            .map_err(|error| source.diagnose(error, index, span))
+            .wrap_err_with(|| format!("Failed to apply mod {}", id))?;
+    }
+    Ok(())
+}
+
+fn apply_mod(
+    mod_context: &mut ModContext,
+    manifest: &Manifest,
+    dir: &Path,
+    mut lu_mod: Mod,
+) -> eyre::Result<()> {
+    // Mod ids are namespaced by the pack they're defined in
+    let id = qualify_id(&mod_context.packs, &manifest.name, &lu_mod.id);
+    if !id.starts_with(&format!("{}:", manifest.name)) {
+        return Err(eyre!(
+            "Mod {} in pack {} uses the namespace of another pack",
+            lu_mod.id,
+            manifest.name
+        ))
+        .suggestion("Mods can only be defined in their own pack, remove the pack prefix.");
+    }
+    lu_mod.id = id;
+    lu_mod.pack = manifest.name.clone();
+    lu_mod.components = lu_mod
+        .components
+        .iter()
+        .map(|component| qualify_id(&mod_context.packs, &manifest.name, component))
+        .collect();
+    lu_mod.dir = dir.into();
+    manifest
+        .substitute_variables(&mut lu_mod)
+        .wrap_err("Failed to substitute variables")?;
+    lu_mod.init_output_values();
+
+    let first_sub_mod = mod_context.mods.len();
+    match lu_mod.mod_type.as_str() {
+        "item" => apply_item_mod(mod_context, &mut lu_mod)?,
+        "sql" => apply_sql_mod(mod_context, &mut lu_mod)?,
+        "environmental" => apply_environmental_mod(mod_context, &mut lu_mod)?,
+        "mission" => apply_mission_mod(mod_context, &mut lu_mod)?,
+        "npc" => apply_npc_mod(mod_context, &mut lu_mod)?,
+        "enemy" => apply_enemy_mod(mod_context, &mut lu_mod)?,
+        "object" => apply_object_mod(mod_context, &mut lu_mod)?,
+        "locale" => apply_locale_mod(mod_context, &mut lu_mod)?,
+        _ => apply_component_mod(mod_context, &mut lu_mod)?,
+    };
 
-        mod_context.mods.push(lu_mod.clone()); // ehhh
+    // Mods created while applying this one belong to the same pack and
+    // inherit its show-defaults setting
+    for sub_mod in &mut mod_context.mods[first_sub_mod..] {
+        sub_mod.pack = lu_mod.pack.clone();
+        if sub_mod.show_defaults.is_none() {
+            sub_mod.show_defaults = lu_mod.show_defaults;
+        }
     }
+
+    mod_context.mods.push(lu_mod);
     Ok(())
 }
 
+/// Print the row that each mod will insert, grouped by target table, marking
+/// where every value came from.
+fn print_effective_rows(mod_context: &ModContext, show_all: bool) -> eyre::Result<()> {
+    let shown_mods = mod_context
+        .mods
+        .iter()
+        .filter(|m| (show_all || m.show_defaults == Some(true)) && !m.fields.is_empty())
+        .collect::<Vec<&Mod>>();
+
+    let mut table_names = Vec::<String>::new();
+    for lu_mod in &shown_mods {
+        let table_name = lu_mod.get_target_table_name();
+        if !table_names.contains(&table_name) {
+            table_names.push(table_name);
+        }
+    }
+
+    for table_name in table_names {
+        println!("\n{}:", table_name);
+        let table = get_table(&mod_context.database, &table_name)?;
+        for lu_mod in shown_mods
+            .iter()
+            .filter(|m| m.get_target_table_name() == table_name)
+        {
+            println!("  {}", lu_mod.id);
+            for (column, field) in table.column_iter().zip(lu_mod.fields.iter()) {
+                let value = match field {
+                    OutputValue::Known(field) => format_field(field),
+                    _ => String::from("?"),
+                };
+                let source = match lu_mod.value_source(&column.name()) {
+                    Some(source) => format!("[{}]", source),
+                    None => String::new(),
+                };
+                println!("    {:<32} {:<40} {}", column.name(), value, source);
+            }
+        }
+    }
+    println!();
+    Ok(())
+}
+
+fn format_field(field: &Field) -> String {
+    match field {
+        Field::Nothing => String::from("NULL"),
+        Field::Integer(i) => i.to_string(),
+        Field::Float(f) => f.to_string(),
+        Field::Text(t) | Field::VarChar(t) => format!("{:?}", t),
+        Field::Boolean(b) => b.to_string(),
+        Field::BigInt(i) => i.to_string(),
+    }
+}
+
+/// The profile selected with `--profile`, if any.
+fn selected_profile(configuration: &Mods, opts: &Options) -> eyre::Result<Option<Profile>> {
+    match &opts.profile {
+        Some(profile_name) => {
+            let profile = configuration.profiles.get(profile_name);
+            if profile.is_none() {
+                let profile_names = configuration
+                    .profiles
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<String>>();
+                return Err(eyre!(
+                    "Profile {} is not defined in mods.json",
+                    profile_name
+                ))
+                .suggestion(format!(
+                    "Available profiles: {}",
+                    if profile_names.is_empty() {
+                        String::from("none")
+                    } else {
+                        profile_names.join(", ")
+                    }
+                ));
+            }
+            println!("Using profile: {}", profile_name);
+            Ok(profile.cloned())
+        }
+        None => Ok(None),
+    }
+}
+
+/// How a pack directory is written in mods.json: relative to mods.json, with
+/// forward slashes, e.g. `packs/pirates`.
+fn pack_directory_key(dir: &Path) -> String {
+    let dir = dir.strip_prefix(".").unwrap_or(dir);
+    dir.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The database ID of a mod referenced by the mod `mod_id` of `pack`.
+fn lookup_reference(
+    packs: &[String],
+    lookup: &BTreeMap<String, i32>,
+    pack: &str,
+    mod_id: &str,
+    id: &str,
+) -> eyre::Result<i32> {
+    let id = qualify_id(packs, pack, id);
+    match lookup.get(&id) {
+        Some(id) => Ok(*id),
+        None => Err(eyre!(
+            "Mod {} references an object with ID {}, but no database ID was generated for this.",
+            mod_id,
+            id
+        ))
+        .suggestion(format!(
+            "Either remove the reference to {} from your mods, or add a mod with that as ID.",
+            id
+        )),
+    }
+}
+
+/// Resolve a mod id referenced from a pack: ids that start with the name of a
+/// pack followed by a colon refer to that pack, other ids to the given pack.
+fn qualify_id(packs: &[String], pack: &str, id: &str) -> String {
+    match id.split_once(':') {
+        Some((prefix, _)) if packs.iter().any(|p| p == prefix) => id.to_string(),
+        _ => format!("{}:{}", pack, id),
+    }
+}
+
 fn get_mods_for_table<'a>(
     mod_context: &'a ModContext,
     target_table: &'a str,
@@ -572,36 +1293,38 @@ where
     }
 }
 
-fn read_xml<T>(path: &Path) -> eyre::Result<T>
-where
-    T: serde::de::DeserializeOwned + serde::Serialize + std::fmt::Debug,
-{
-    let contents = std::fs::read_to_string(&path)?;
-    let xml: T = quick_xml::de::from_str(&contents)?;
-    Ok(xml)
-}
-
-fn write_xml<T>(content: T, path: &Path) -> eyre::Result<()>
+fn write_json<T>(content: T, path: &Path) -> eyre::Result<()>
 where
     T: serde::Serialize + std::fmt::Debug,
 {
-    let mut writer = BufWriter::new(File::create(path)?);
-    let _ = writer.write(b"<?xml version=\"1.0\" encoding=\"UTF-8\" ?>");
-    quick_xml::se::to_writer(&mut writer, &content)?;
+    let mut writer = BufWriter::new(File::create(&path)?);
+    serde_json::to_writer(&mut writer, &content)?;
     Ok(())
 }
 
-fn write_json<T>(content: T, path: &Path) -> eyre::Result<()>
+fn write_json_pretty<T>(content: T, path: &Path) -> eyre::Result<()>
 where
     T: serde::Serialize + std::fmt::Debug,
 {
-    let mut writer = BufWriter::new(File::create(&path)?);
-    serde_json::to_writer(&mut writer, &content)?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, &content)?;
     Ok(())
 }
 
-fn print_timer(start: Instant) -> Instant {
-    let duration = start.elapsed();
-    println!("{}ms", duration.as_millis());
-    Instant::now()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qualified_ids() {
+        let packs = vec![String::from("pirates"), String::from("ninjas")];
+        assert_eq!(qualify_id(&packs, "pirates", "sword"), "pirates:sword");
+        assert_eq!(qualify_id(&packs, "pirates", "ninjas:star"), "ninjas:star");
+        assert_eq!(
+            qualify_id(&packs, "pirates", "pirates:sword"),
+            "pirates:sword"
+        );
+        // prefixes that aren't packs are part of the id
+        assert_eq!(qualify_id(&packs, "ninjas", "item:hat"), "ninjas:item:hat");
+    }
 }
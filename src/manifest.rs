@@ -1,10 +1,136 @@
+use crate::lu_mod::Mod;
+use color_eyre::{
+    eyre::{self, eyre, WrapErr},
+    Section,
+};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Manifest {
     pub name: String,
     pub files: Vec<PathBuf>,
+    /// Constants that mods of this pack can use as `${name}`.
+    #[serde(default)]
+    pub variables: BTreeMap<String, JsonValue>,
+}
+
+impl Manifest {
+    /// Paths of the mod files of this pack, relative to the working directory.
+    /// Entries of `files` containing glob patterns such as `items/**/*.json`
+    /// are expanded, in alphabetical order.
+    pub fn mod_files(&self, pack_dir: &Path) -> eyre::Result<Vec<PathBuf>> {
+        let mut mod_files = Vec::new();
+        for file in &self.files {
+            let file_str = file.to_string_lossy();
+            if !file_str.contains(&['*', '?', '['][..]) {
+                mod_files.push(pack_dir.join(file));
+                continue;
+            }
+
+            let pattern = format!(
+                "{}/{}",
+                glob::Pattern::escape(&pack_dir.to_string_lossy()),
+                file_str
+            );
+            let mut matches = glob::glob(&pattern)
+                .wrap_err_with(|| format!("Invalid file pattern {} in {}", file_str, self.name))?
+                .collect::<Result<Vec<PathBuf>, _>>()?;
+            // the manifest itself is never a mod file, even if a pattern like *.json matches it
+            matches.retain(|path| path.is_file() && !path.ends_with("manifest.json"));
+            matches.sort();
+            for path in matches {
+                if !mod_files.contains(&path) {
+                    mod_files.push(path);
+                }
+            }
+        }
+        Ok(mod_files)
+    }
+
+    /// Replace `${name}` references to the variables of this pack in the
+    /// values, locale and tasks of a mod.
+    pub fn substitute_variables(&self, lu_mod: &mut Mod) -> eyre::Result<()> {
+        for value in lu_mod.values.values_mut() {
+            self.substitute_json(value)?;
+        }
+        for text in lu_mod.locale.values_mut() {
+            *text = self.substitute_str(text)?;
+        }
+        for value in lu_mod.items.iter_mut().chain(lu_mod.skills.iter_mut()) {
+            self.substitute_json(value)?;
+        }
+        for task in lu_mod.tasks.iter_mut() {
+            self.substitute_json(&mut task.target)?;
+            for value in task.group.iter_mut() {
+                self.substitute_json(value)?;
+            }
+            for text in task.locale.values_mut() {
+                *text = self.substitute_str(text)?;
+            }
+            task.icon = self.substitute_str(&task.icon)?;
+            task.small_icon = self.substitute_str(&task.small_icon)?;
+        }
+        Ok(())
+    }
+
+    /// Substitute variables in all strings inside a JSON value. A string that
+    /// is just a reference is replaced by the variable's value as it is, so
+    /// that numbers stay numbers.
+    fn substitute_json(&self, value: &mut JsonValue) -> eyre::Result<()> {
+        match value {
+            JsonValue::String(text) => {
+                let whole_reference = text
+                    .strip_prefix("${")
+                    .and_then(|rest| rest.strip_suffix('}'))
+                    .filter(|name| !name.contains('}'));
+                if let Some(name) = whole_reference {
+                    *value = self.variable(name)?.clone();
+                } else {
+                    *text = self.substitute_str(text)?;
+                }
+            }
+            JsonValue::Array(values) => {
+                for value in values.iter_mut() {
+                    self.substitute_json(value)?;
+                }
+            }
+            JsonValue::Object(values) => {
+                for value in values.values_mut() {
+                    self.substitute_json(value)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn substitute_str(&self, text: &str) -> eyre::Result<String> {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            output.push_str(&rest[..start]);
+            let length = rest[start..]
+                .find('}')
+                .ok_or_else(|| eyre!("Unclosed variable reference in {:?}", text))?;
+            match self.variable(&rest[start + 2..start + length])? {
+                JsonValue::String(value) => output.push_str(value),
+                value => output.push_str(&value.to_string()),
+            }
+            rest = &rest[start + length + 1..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    fn variable(&self, name: &str) -> eyre::Result<&JsonValue> {
+        self.variables
+            .get(name)
+            .ok_or_else(|| eyre!("Unknown variable ${{{}}} in pack {}", name, self.name))
+            .suggestion("Add the variable to \"variables\" in the pack's manifest.json.")
+    }
 }
 
 impl Default for Manifest {
@@ -12,6 +138,56 @@ impl Default for Manifest {
         Self {
             name: "mod-name".to_string(),
             files: vec![PathBuf::from("mod.json")],
+            variables: BTreeMap::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> Manifest {
+        let mut manifest = Manifest {
+            name: String::from("pirates"),
+            ..Default::default()
+        };
+        manifest
+            .variables
+            .insert(String::from("ship"), JsonValue::from("Black Pearl"));
+        manifest
+            .variables
+            .insert(String::from("damage"), JsonValue::from(3));
+        manifest
+    }
+
+    #[test]
+    fn substitutes_variables_in_text() {
+        let manifest = manifest();
+        assert_eq!(
+            manifest.substitute_str("${ship} deals ${damage}").unwrap(),
+            "Black Pearl deals 3"
+        );
+        assert_eq!(
+            manifest.substitute_str("no variables").unwrap(),
+            "no variables"
+        );
+        assert_eq!(
+            manifest.substitute_str("Épée ${ship}!").unwrap(),
+            "Épée Black Pearl!"
+        );
+        assert!(manifest.substitute_str("${ship").is_err());
+        assert!(manifest.substitute_str("${captain}").is_err());
+    }
+
+    #[test]
+    fn whole_references_keep_their_type() {
+        let manifest = manifest();
+        let mut value = serde_json::json!({"damage": "${damage}", "name": ["the ${ship}"]});
+        manifest.substitute_json(&mut value).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"damage": 3, "name": ["the Black Pearl"]})
+        );
+    }
+}
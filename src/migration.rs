@@ -0,0 +1,238 @@
+use crate::json_edit::{set_string_members, SpannedJson, TextEdits};
+use crate::mod_context::LookupFile;
+use crate::mods::Mods;
+use color_eyre::{
+    eyre::{self, eyre},
+    Section,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Version of the mods.json and lookup.json formats written by this tool.
+///
+/// Version 1 is the format of InfectedRose and of earlier versions of this
+/// tool. Version 2 prefixes mod ids in lookup.json with the name of their pack
+/// and adds packs and profiles to mods.json.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// How the format version of a file compares to the one of this tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionCheck {
+    UpToDate,
+    /// Written in an older format, which needs to be migrated.
+    Older(u32),
+    /// Written by a newer version of the tool, which may use settings this one
+    /// doesn't know about.
+    Newer(u32),
+}
+
+/// Compare the `version` of a file against `FORMAT_VERSION`. Files without a
+/// version are treated as version 1, and only the major part of versions like
+/// `1.0` is considered.
+pub fn check_version(file_name: &str, version: &str) -> eyre::Result<VersionCheck> {
+    let version = version.trim();
+    let major = if version.is_empty() {
+        1
+    } else {
+        let major = version.split('.').next().unwrap_or_default();
+        major
+            .parse::<u32>()
+            .map_err(|_| eyre!("Invalid version {:?} in {}", version, file_name))
+            .suggestion(format!(
+                "Set the version to {:?}, the format written by this version of lu-mod-tool.",
+                FORMAT_VERSION.to_string()
+            ))?
+            .max(1)
+    };
+    Ok(match major.cmp(&FORMAT_VERSION) {
+        std::cmp::Ordering::Less => VersionCheck::Older(major),
+        std::cmp::Ordering::Equal => VersionCheck::UpToDate,
+        std::cmp::Ordering::Greater => VersionCheck::Newer(major),
+    })
+}
+
+/// Bring mods.json up to date. Returns the version it had, if it was `Older`
+/// it was migrated and its version needs to be written back with
+/// `write_mods_version`.
+pub fn migrate_mods(configuration: &mut Mods) -> eyre::Result<VersionCheck> {
+    let check = check_version("mods.json", &configuration.version)?;
+    if let VersionCheck::Older(version) = check {
+        // packs and profiles were added in version 2 and default to empty,
+        // so only the version itself changes
+        println!(
+            "Migrating mods.json from version {} to {}",
+            version, FORMAT_VERSION
+        );
+        configuration.version = FORMAT_VERSION.to_string();
+    }
+    Ok(check)
+}
+
+/// Prefix the ids in a lookup.json from before version 2 with the names of
+/// their packs, given the prefixed ids of the mods. Returns whether every id
+/// in the lookup is prefixed now.
+pub fn migrate_lookup_ids<'a>(
+    ids: &mut BTreeMap<String, i32>,
+    packs: &[String],
+    mod_ids: impl IntoIterator<Item = &'a str>,
+) -> bool {
+    for mod_id in mod_ids {
+        if ids.contains_key(mod_id) {
+            continue;
+        }
+        let unprefixed_id = mod_id.split_once(':').map(|(_, id)| id);
+        if let Some(id) = unprefixed_id.and_then(|id| ids.remove(id)) {
+            ids.insert(mod_id.to_string(), id);
+        }
+    }
+    ids.keys()
+        .all(|id| matches!(id.split_once(':'), Some((pack, _)) if packs.iter().any(|p| p == pack)))
+}
+
+/// Write the version of a migrated mods.json. Only the `version` member is
+/// changed, so the layout of the file and settings this tool doesn't know
+/// about, like the ones of InfectedRose, are kept.
+pub fn write_mods_version(path: &Path, version: &str) -> eyre::Result<()> {
+    let text = std::fs::read_to_string(path)?;
+    let root = SpannedJson::parse(&text)?;
+    let mut edits = TextEdits::default();
+    set_string_members(
+        &text,
+        &root,
+        &[(String::from("version"), version.to_string())],
+        &mut edits,
+    )?;
+    std::fs::write(path, edits.apply(&text))?;
+    Ok(())
+}
+
+/// The layouts lookup.json has had: an object with an `ids` map, written by
+/// InfectedRose and this tool, or a map from mod id to database ID.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyLookupFile {
+    Current(LookupFile),
+    Flat(BTreeMap<String, i32>),
+}
+
+/// Read lookup.json in any of its layouts, creating it if it doesn't exist.
+/// The version of the returned file is the one that was read.
+pub fn read_lookup(path: &Path) -> eyre::Result<LookupFile> {
+    if !path.exists() {
+        let lookup = LookupFile::default();
+        std::fs::write(path, serde_json::to_string(&lookup)?)?;
+        return Ok(lookup);
+    }
+    let contents = std::fs::read_to_string(path)?;
+    match serde_json::from_str::<AnyLookupFile>(&contents)? {
+        AnyLookupFile::Current(lookup) => Ok(lookup),
+        AnyLookupFile::Flat(ids) => Ok(LookupFile {
+            version: String::new(),
+            ids,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert_eq!(
+            check_version("lookup.json", "").unwrap(),
+            VersionCheck::Older(1)
+        );
+        assert_eq!(
+            check_version("lookup.json", "1.0").unwrap(),
+            VersionCheck::Older(1)
+        );
+        assert_eq!(
+            check_version("lookup.json", "0").unwrap(),
+            VersionCheck::Older(1)
+        );
+        assert_eq!(
+            check_version("lookup.json", " 2 ").unwrap(),
+            VersionCheck::UpToDate
+        );
+        assert_eq!(
+            check_version("lookup.json", "3.1").unwrap(),
+            VersionCheck::Newer(3)
+        );
+        assert!(check_version("lookup.json", "two").is_err());
+    }
+
+    #[test]
+    fn reads_flat_and_current_lookup_files() {
+        let dir = std::env::temp_dir().join(format!("lu-mod-tool-lookup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let flat = dir.join("flat.json");
+        std::fs::write(&flat, r#"{"sword": 20000, "shield": 20001}"#).unwrap();
+        let lookup = read_lookup(&flat).unwrap();
+        assert_eq!(lookup.version, "");
+        assert_eq!(lookup.ids["shield"], 20001);
+
+        let current = dir.join("current.json");
+        std::fs::write(
+            &current,
+            r#"{"version": "2", "ids": {"pirates:sword": 20000}}"#,
+        )
+        .unwrap();
+        let lookup = read_lookup(&current).unwrap();
+        assert_eq!(lookup.version, "2");
+        assert_eq!(lookup.ids["pirates:sword"], 20000);
+
+        let missing = dir.join("missing.json");
+        assert!(read_lookup(&missing).unwrap().ids.is_empty());
+        assert!(missing.is_file());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn migrates_lookup_ids() {
+        let packs = vec!["pirates".to_string(), "ninjas".to_string()];
+        let mut ids = BTreeMap::new();
+        ids.insert("sword".to_string(), 20000);
+        ids.insert("ninjas:star".to_string(), 20001);
+        ids.insert("hat".to_string(), 20002);
+
+        assert!(!migrate_lookup_ids(
+            &mut ids,
+            &packs,
+            vec!["pirates:sword", "ninjas:star"]
+        ));
+        assert_eq!(ids["pirates:sword"], 20000);
+        assert_eq!(ids["ninjas:star"], 20001);
+        assert!(!ids.contains_key("sword"));
+
+        // the hat's pack was disabled, or failed to read, in the first pass
+        assert!(migrate_lookup_ids(&mut ids, &packs, vec!["ninjas:hat"]));
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids["ninjas:hat"], 20002);
+    }
+
+    #[test]
+    fn writes_only_the_version_of_mods_json() {
+        let dir =
+            std::env::temp_dir().join(format!("lu-mod-tool-mods-json-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("mods.json");
+        let text = "{\n    \"version\": \"1.0\",\n    \"database\": \"cdclient.fdb\",\n    \"sqlite\": \"CDServer.sqlite\",\n    \"priorities\": [],\n    \"copy\": {\"enabled\": true}\n}\n";
+        std::fs::write(&path, text).unwrap();
+
+        let mut configuration = serde_json::from_str::<Mods>(text).unwrap();
+        assert_eq!(
+            migrate_mods(&mut configuration).unwrap(),
+            VersionCheck::Older(1)
+        );
+        write_mods_version(&path, &configuration.version).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            text.replace("\"1.0\"", "\"2\"")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,9 @@
-use crate::HashMap;
-use color_eyre::eyre::{self, eyre};
+use crate::diagnostics::ValueError;
+use crate::lu_mod::IdPart;
+use color_eyre::eyre;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MissionOffer {
@@ -23,7 +25,50 @@ pub struct MissionTask {
     pub icon: String,
     #[serde(rename = "small-icon")]
     pub small_icon: String,
-    pub locale: HashMap<String, String>,
+    pub locale: BTreeMap<String, String>,
+}
+
+/// The `rewards` of a mission mod, filled in the `reward_*` columns. Items and
+/// emotes are LOTs or the ids of mods.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MissionRewards {
+    #[serde(default)]
+    pub items: Vec<RewardItem>,
+    /// Items for completing the mission again, if it is repeatable.
+    #[serde(default)]
+    pub items_repeatable: Vec<RewardItem>,
+    #[serde(default)]
+    pub emotes: Vec<JsonValue>,
+    pub currency: Option<i32>,
+    pub currency_repeatable: Option<i32>,
+    pub reputation: Option<i32>,
+    pub maxwallet: Option<i32>,
+}
+
+/// An item reward, either just the item or the item and how many are given.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum RewardItem {
+    WithCount {
+        item: JsonValue,
+        #[serde(default = "one")]
+        count: i32,
+    },
+    Item(JsonValue),
+}
+
+impl RewardItem {
+    pub fn item_and_count(&self) -> (&JsonValue, i32) {
+        match self {
+            RewardItem::WithCount { item, count } => (item, *count),
+            RewardItem::Item(item) => (item, 1),
+        }
+    }
+}
+
+fn one() -> i32 {
+    1
 }
 
 pub fn parse_mission_task_type(input: &str) -> eyre::Result<i32> {
@@ -47,7 +92,159 @@ pub fn parse_mission_task_type(input: &str) -> eyre::Result<i32> {
         "PlayerFlag" => Ok(24),
         "VisitProperty" => Ok(30),
         _ => {
-            return Err(eyre!("Unknown mission task type: {}", input));
+            return Err(ValueError::new(
+                "type",
+                &JsonValue::from(input),
+                format!("Unknown mission task type: {}", input),
+            )
+            .into());
+        }
+    }
+}
+
+/// Parse the prerequisites of a mission into the expression syntax of
+/// `prereqMissionID`, where `,` means all of and `|` any of. Missions are
+/// given by mod id or database ID, either
+/// - as an expression in that syntax, like `"quest_1,(quest_2|1733)"`, or
+/// - as a list of missions that all need to be completed, where a list inside
+///   the list is a choice of missions, a list inside that all of them again,
+///   and so on.
+pub fn parse_prerequisites(value: &JsonValue) -> eyre::Result<Vec<IdPart>> {
+    prerequisite_parts(value, true)
+}
+
+fn prerequisite_parts(value: &JsonValue, all: bool) -> eyre::Result<Vec<IdPart>> {
+    match value {
+        JsonValue::Number(number) => match number.as_i64() {
+            Some(id) => Ok(vec![IdPart::Text(id.to_string())]),
+            None => Err(ValueError::new(
+                "prerequisites",
+                value,
+                "Database IDs of prerequisites need to be integers",
+            )
+            .into()),
+        },
+        JsonValue::String(expression) => parse_prerequisite_expression(expression, value),
+        JsonValue::Array(items) => {
+            let mut parts = vec![];
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    parts.push(IdPart::Text(String::from(if all { "," } else { "|" })));
+                }
+                let item_parts = prerequisite_parts(item, !all)?;
+                // Parenthesize combinations, so they don't mix with this list
+                if item_parts.len() > 1 {
+                    parts.push(IdPart::Text(String::from("(")));
+                    parts.extend(item_parts);
+                    parts.push(IdPart::Text(String::from(")")));
+                } else {
+                    parts.extend(item_parts);
+                }
+            }
+            Ok(parts)
+        }
+        _ => Err(ValueError::new(
+            "prerequisites",
+            value,
+            "Prerequisites need to be a mission, an expression or a list of missions",
+        )
+        .into()),
+    }
+}
+
+/// Split an expression like `quest_1,(quest_2|1733)` into operators, database
+/// IDs and mod ids.
+fn parse_prerequisite_expression(expression: &str, value: &JsonValue) -> eyre::Result<Vec<IdPart>> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut word = String::new();
+    for c in expression.chars().chain(std::iter::once(' ')) {
+        if !matches!(c, ',' | '|' | '(' | ')') && !c.is_whitespace() {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            // Stock prerequisites can have a mission state after the ID, like 1733:2
+            let is_database_id = word
+                .split(':')
+                .all(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+            parts.push(if is_database_id {
+                IdPart::Text(word.clone())
+            } else {
+                IdPart::Id(word.clone())
+            });
+            word.clear();
+        }
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                return Err(ValueError::new(
+                    "prerequisites",
+                    value,
+                    "Unmatched ) in the prerequisites",
+                )
+                .into())
+            }
+            ')' => depth -= 1,
+            _ => {}
         }
+        if !c.is_whitespace() {
+            parts.push(IdPart::Text(c.to_string()));
+        }
+    }
+    if depth > 0 {
+        return Err(
+            ValueError::new("prerequisites", value, "Unmatched ( in the prerequisites").into(),
+        );
+    }
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The parts as an expression, with mod ids in braces.
+    fn render(parts: &[IdPart]) -> String {
+        parts
+            .iter()
+            .map(|part| match part {
+                IdPart::Text(text) => text.clone(),
+                IdPart::Id(id) => format!("{{{}}}", id),
+            })
+            .collect()
+    }
+
+    fn parse(expression: &str) -> eyre::Result<Vec<IdPart>> {
+        parse_prerequisite_expression(expression, &JsonValue::from(expression))
+    }
+
+    #[test]
+    fn parses_prerequisite_expressions() {
+        assert_eq!(
+            render(&parse("quest_1, (quest_2|1733)").unwrap()),
+            "{quest_1},({quest_2}|1733)"
+        );
+        assert_eq!(
+            render(&parse("1733:2|ninjas:intro").unwrap()),
+            "1733:2|{ninjas:intro}"
+        );
+        assert_eq!(
+            parse("1733").unwrap(),
+            vec![IdPart::Text(String::from("1733"))]
+        );
+        assert!(parse("(quest_1").is_err());
+        assert!(parse("quest_1)").is_err());
+    }
+
+    #[test]
+    fn parses_prerequisite_lists() {
+        let value = serde_json::json!(["quest_1", ["quest_2", ["quest_3", 42]], "a|b"]);
+        assert_eq!(
+            render(&parse_prerequisites(&value).unwrap()),
+            "{quest_1},({quest_2}|({quest_3},42)),({a}|{b})"
+        );
+        assert!(parse_prerequisites(&serde_json::json!(1.5)).is_err());
+        assert!(parse_prerequisites(&serde_json::json!({"id": 1})).is_err());
     }
 }
@@ -1,9 +1,10 @@
 use crate::locale::*;
 use crate::lu_mod::Mod;
+use crate::migration::FORMAT_VERSION;
 use crate::mods::*;
 use assembly_fdb::mem::Database;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 pub struct ModContext<'a> {
@@ -11,10 +12,12 @@ pub struct ModContext<'a> {
     pub root: PathBuf,
     pub database: Database<'a>,
     pub localization: Localization,
-    pub ids: HashMap<String, u32>,
+    pub ids: BTreeMap<String, u32>,
     pub mods: Vec<Mod>,
     pub server_sql: Vec<String>,
-    pub lookup: HashMap<String, i32>,
+    pub lookup: BTreeMap<String, i32>,
+    /// Names of all packs that are applied, which are the namespaces of mod ids.
+    pub packs: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -24,7 +27,20 @@ pub struct NewLocaleEntry {
     pub value: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LookupFile {
-    pub ids: HashMap<String, i32>,
+    /// Format version, see `migration::FORMAT_VERSION`. Missing in files
+    /// written before lookup.json had a version.
+    #[serde(default)]
+    pub version: String,
+    pub ids: BTreeMap<String, i32>,
+}
+
+impl Default for LookupFile {
+    fn default() -> Self {
+        Self {
+            version: FORMAT_VERSION.to_string(),
+            ids: BTreeMap::new(),
+        }
+    }
 }
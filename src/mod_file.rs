@@ -0,0 +1,463 @@
+use crate::diagnostics::{offset_of, offset_of_byte_column, without_position, SourceFile};
+use crate::lu_mod::Mod;
+use crate::script::run_script;
+use color_eyre::{
+    eyre::{self, eyre, WrapErr},
+    Section,
+};
+use serde::Deserialize;
+use serde_json::{value::RawValue, Map as JsonMap, Value as JsonValue};
+use std::ops::Range;
+use std::path::Path;
+
+/// The formats mod files can be written in, chosen by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModFileFormat {
+    Json,
+    /// JSON5, which also covers JSON with comments and trailing commas.
+    Json5,
+    Yaml,
+    Toml,
+    /// One mod per row, see `read_csv_mods`.
+    Csv,
+    /// A script generating mods, see `run_script`.
+    Rhai,
+}
+
+impl ModFileFormat {
+    pub fn from_path(path: &Path) -> eyre::Result<ModFileFormat> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        match extension.as_str() {
+            "json" => Ok(ModFileFormat::Json),
+            "jsonc" | "json5" => Ok(ModFileFormat::Json5),
+            "yaml" | "yml" => Ok(ModFileFormat::Yaml),
+            "toml" => Ok(ModFileFormat::Toml),
+            "csv" => Ok(ModFileFormat::Csv),
+            "rhai" => Ok(ModFileFormat::Rhai),
+            _ => Err(eyre!("Unsupported mod file {}", path.display())).suggestion(
+                "Mod files need to have one of the extensions .json, .jsonc, .json5, .yaml, .yml, .toml, .csv or .rhai.",
+            ),
+        }
+    }
+}
+
+/// TOML documents can't have an array at the top level, so TOML mod files
+/// list their mods as `[[mods]]` tables.
+#[derive(Deserialize)]
+struct TomlModFile {
+    mods: Vec<Mod>,
+}
+
+/// A parsed mod file.
+pub struct ModFile {
+    pub source: SourceFile,
+    /// The mods in the file, with the part of the file each one was read from,
+    /// if it could be found.
+    pub mods: Vec<(Mod, Option<Range<usize>>)>,
+}
+
+/// Read the list of mods in a mod file, using the parser for its format.
+pub fn read_mod_file(path: &Path) -> eyre::Result<ModFile> {
+    let format = ModFileFormat::from_path(path)?;
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read mod file {}", path.display()))?;
+    let source = SourceFile {
+        path: path.to_path_buf(),
+        contents,
+    };
+    let mods = match format {
+        ModFileFormat::Json => read_json_mods(&source)?,
+        ModFileFormat::Json5 => {
+            let mods: Vec<Mod> =
+                json5::from_str(&source.contents).map_err(|error| match &error {
+                    json5::Error::Message {
+                        msg,
+                        location: Some(location),
+                    } => source.error_at(
+                        msg,
+                        offset_of(&source.contents, location.line, location.column),
+                    ),
+                    _ => eyre!(error),
+                })?;
+            let starts = id_positions(&source.contents, &mods)
+                .into_iter()
+                .map(|position| position.map(|position| object_start(&source.contents, position)))
+                .collect();
+            with_spans(&source.contents, mods, starts)
+        }
+        ModFileFormat::Yaml => {
+            let mods: Vec<Mod> =
+                serde_yaml::from_str(&source.contents).map_err(|error| match error.location() {
+                    Some(location) => source.error_at(
+                        without_position(error.to_string()),
+                        offset_of(&source.contents, location.line(), location.column()),
+                    ),
+                    None => eyre!(error),
+                })?;
+            let starts = item_starts(&source.contents, &mods, |line| {
+                // items of the top level list, but not the `---` document marker
+                line == "-" || line.starts_with("- ") || line.starts_with("-\t")
+            });
+            with_spans(&source.contents, mods, starts)
+        }
+        ModFileFormat::Toml => {
+            let file = toml::from_str::<TomlModFile>(&source.contents).map_err(|error| {
+                match error.line_col() {
+                    // toml counts from zero
+                    Some((line, column)) => source.error_at(
+                        without_position(error.to_string()),
+                        offset_of_byte_column(&source.contents, line + 1, column + 1),
+                    ),
+                    None => eyre!(error),
+                }
+            })?;
+            let starts = item_starts(&source.contents, &file.mods, |line| {
+                let name = line
+                    .trim_start()
+                    .strip_prefix("[[")
+                    .and_then(|header| header.split_once("]]"))
+                    .map(|(name, _)| name.trim());
+                name == Some("mods")
+            });
+            with_spans(&source.contents, file.mods, starts)
+        }
+        ModFileFormat::Csv => read_csv_mods(&source)?,
+        ModFileFormat::Rhai => {
+            run_script(&source).wrap_err_with(|| format!("Failed to run {}", path.display()))?
+        }
+    };
+    Ok(ModFile { source, mods })
+}
+
+/// Where the mods of a file start: at the lines `is_start` accepts, if there
+/// is one per mod, otherwise at their ids.
+fn item_starts(
+    contents: &str,
+    mods: &[Mod],
+    is_start: impl Fn(&str) -> bool,
+) -> Vec<Option<usize>> {
+    let mut starts = Vec::new();
+    let mut offset = 0;
+    for line in contents.split_inclusive('\n') {
+        if is_start(line.trim_end()) {
+            starts.push(Some(offset + line.len() - line.trim_start().len()));
+        }
+        offset += line.len();
+    }
+    if starts.len() == mods.len() {
+        starts
+    } else {
+        id_positions(contents, mods)
+    }
+}
+
+/// Find the ids of mods, in the order of the mods, as the value of an `id`
+/// key. Ids that can't be found, for example because they're written with
+/// escapes, are None.
+fn id_positions(contents: &str, mods: &[Mod]) -> Vec<Option<usize>> {
+    let mut search_start = 0;
+    mods.iter()
+        .map(|lu_mod| {
+            let position = find_id(contents, search_start, &lu_mod.id)?;
+            search_start = position + lu_mod.id.len();
+            Some(position)
+        })
+        .collect()
+}
+
+fn find_id(contents: &str, mut search_start: usize, id: &str) -> Option<usize> {
+    if id.is_empty() {
+        return None;
+    }
+    while let Some(i) = contents[search_start..].find(id) {
+        let position = search_start + i;
+        search_start = position + id.len();
+        // the whole id, after `id:`, `"id": "`, `id = "` and the like
+        let is_id_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | ':');
+        if contents[search_start..].starts_with(is_id_char) {
+            continue;
+        }
+        let before = contents[..position]
+            .trim_end_matches(&['"', '\''][..])
+            .trim_end();
+        let key = match before.strip_suffix(&[':', '='][..]) {
+            Some(key) => key.trim_end().trim_end_matches(&['"', '\''][..]),
+            None => continue,
+        };
+        if let Some(rest) = key.strip_suffix("id") {
+            if !rest.ends_with(is_id_char) {
+                return Some(position);
+            }
+        }
+    }
+    None
+}
+
+/// The brace that opens the object containing an offset, skipping the objects
+/// before it, or the offset itself if there is none.
+fn object_start(contents: &str, offset: usize) -> usize {
+    let mut depth = 0;
+    for (index, c) in contents[..offset].char_indices().rev() {
+        match c {
+            '}' => depth += 1,
+            '{' if depth == 0 => return index,
+            '{' => depth -= 1,
+            _ => {}
+        }
+    }
+    offset
+}
+
+/// Pair mods with spans from their start to the start of the next mod.
+fn with_spans(
+    contents: &str,
+    mods: Vec<Mod>,
+    starts: Vec<Option<usize>>,
+) -> Vec<(Mod, Option<Range<usize>>)> {
+    let ends = (0..starts.len())
+        .map(|index| {
+            starts[index + 1..]
+                .iter()
+                .flatten()
+                .next()
+                .copied()
+                .unwrap_or(contents.len())
+        })
+        .collect::<Vec<usize>>();
+    mods.into_iter()
+        .zip(starts)
+        .zip(ends)
+        .map(|((lu_mod, start), end)| (lu_mod, start.map(|start| start..end.max(start))))
+        .collect()
+}
+
+/// Parse a JSON mod file in two steps, first into the raw text of every mod
+/// and then each of those into a mod, to know where in the file each mod is.
+fn read_json_mods(source: &SourceFile) -> eyre::Result<Vec<(Mod, Option<Range<usize>>)>> {
+    let contents = &source.contents;
+    let raw_mods: Vec<&RawValue> =
+        serde_json::from_str(contents).map_err(|error| json_error(source, 0, error))?;
+    raw_mods
+        .into_iter()
+        .map(|raw_mod| {
+            let start = raw_mod.get().as_ptr() as usize - contents.as_ptr() as usize;
+            let lu_mod = serde_json::from_str(raw_mod.get())
+                .map_err(|error| json_error(source, start, error))?;
+            Ok((lu_mod, Some(start..start + raw_mod.get().len())))
+        })
+        .collect()
+}
+
+/// Point a serde_json error at the file, given the offset of the text that
+/// was parsed.
+fn json_error(source: &SourceFile, start: usize, error: serde_json::Error) -> eyre::Report {
+    let offset =
+        start + offset_of_byte_column(&source.contents[start..], error.line(), error.column());
+    source.error_at(without_position(error.to_string()), offset)
+}
+
+/// Convert the rows of a CSV file to mods.
+///
+/// The `id`, `type`, `action`, `table` and `show-defaults` columns set the
+/// fields of the same name, and `components`, `items` and `skills` are lists
+/// separated by `;`. Columns named `locale.<locale>` go into `locale`, all
+/// other columns go into `values`. Only the cells of values, items and skills
+/// are read as numbers, booleans or null, the others are always text. Empty
+/// cells are left out, so the defaults of the mod type apply.
+fn read_csv_mods(source: &SourceFile) -> eyre::Result<Vec<(Mod, Option<Range<usize>>)>> {
+    let contents = &source.contents;
+    let mut reader = csv::Reader::from_reader(contents.as_bytes());
+    let headers = reader.headers()?.clone();
+    let mut mods = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let (line, start) = record
+            .position()
+            .map(|position| (position.line(), position.byte() as usize))
+            .unwrap_or((0, 0));
+        let end = contents[start..]
+            .find('\n')
+            .map(|i| start + i)
+            .unwrap_or_else(|| contents.len());
+
+        let mut object = JsonMap::new();
+        let mut locale = JsonMap::new();
+        let mut values = JsonMap::new();
+        object.insert(String::from("action"), JsonValue::from("add"));
+        for (header, cell) in headers.iter().zip(record.iter()) {
+            if cell.is_empty() {
+                continue;
+            }
+            match header {
+                "id" | "type" | "action" | "table" => {
+                    object.insert(header.to_string(), JsonValue::from(cell));
+                }
+                "show-defaults" => {
+                    object.insert(header.to_string(), parse_csv_cell(cell));
+                }
+                "components" => {
+                    let list = cell
+                        .split(';')
+                        .map(|entry| JsonValue::from(entry.trim()))
+                        .collect();
+                    object.insert(header.to_string(), JsonValue::Array(list));
+                }
+                "items" | "skills" => {
+                    let list = cell
+                        .split(';')
+                        .map(|entry| parse_csv_cell(entry.trim()))
+                        .collect();
+                    object.insert(header.to_string(), JsonValue::Array(list));
+                }
+                _ => {
+                    if let Some(locale_name) = header.strip_prefix("locale.") {
+                        locale.insert(locale_name.to_string(), JsonValue::from(cell));
+                    } else {
+                        values.insert(header.to_string(), parse_csv_cell(cell));
+                    }
+                }
+            }
+        }
+        object.insert(String::from("locale"), JsonValue::Object(locale));
+        object.insert(String::from("values"), JsonValue::Object(values));
+
+        let lu_mod = serde_json::from_value(JsonValue::Object(object)).map_err(|error| {
+            source.error_at(
+                format!("Invalid mod in CSV row on line {}: {}", line, error),
+                start,
+            )
+        })?;
+        mods.push((lu_mod, Some(start..end)));
+    }
+    Ok(mods)
+}
+
+/// Interpret a CSV cell as a number, boolean or null if it looks like one,
+/// and as text otherwise.
+fn parse_csv_cell(cell: &str) -> JsonValue {
+    match serde_json::from_str::<JsonValue>(cell) {
+        Ok(value) if value.is_number() || value.is_boolean() || value.is_null() => value,
+        _ => JsonValue::from(cell),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn csv_cells_are_only_typed_in_values() {
+        let source = SourceFile {
+            path: PathBuf::from("pirates/items.csv"),
+            contents: String::from(
+                "id,type,components,items,locale.en_US,damage,scale.x,name\n\
+                 1000,item,12;pirates:hat,7415;true,42,1.5,2,null\n",
+            ),
+        };
+        let mods = read_csv_mods(&source).unwrap();
+        let lu_mod = &mods[0].0;
+        assert_eq!(lu_mod.id, "1000");
+        assert_eq!(lu_mod.components, vec!["12", "pirates:hat"]);
+        assert_eq!(
+            lu_mod.items,
+            vec![JsonValue::from(7415), JsonValue::from(true)]
+        );
+        assert_eq!(lu_mod.locale["en_US"], "42");
+        assert_eq!(lu_mod.values["damage"], JsonValue::from(1.5));
+        assert_eq!(lu_mod.values["scale.x"], JsonValue::from(2));
+        assert_eq!(lu_mod.values["name"], JsonValue::Null);
+    }
+
+    /// Read a mod file written to a temporary directory.
+    fn read(name: &str, contents: &str) -> ModFile {
+        let dir = std::env::temp_dir().join(format!("lu-mod-tool-mods-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        let mod_file = read_mod_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        mod_file
+    }
+
+    /// The text of the span of each mod.
+    fn spans(mod_file: &ModFile) -> Vec<Option<&str>> {
+        mod_file
+            .mods
+            .iter()
+            .map(|(_, span)| span.clone().map(|span| &mod_file.source.contents[span]))
+            .collect()
+    }
+
+    const YAML_SWORD: &str = "- id: sword\n  type: item\n  action: add\n  locale: {}\n  values:\n    level: high\n  items:\n    - 1\n";
+    const YAML_SHIELD: &str =
+        "- type: item\n  action: add\n  locale: {}\n  values: {}\n  id: shield\n";
+
+    #[test]
+    fn yaml_mods_span_their_list_items() {
+        let mod_file = read(
+            "mods.yaml",
+            &format!("---\n# épées\n{}{}", YAML_SWORD, YAML_SHIELD),
+        );
+        assert_eq!(spans(&mod_file), vec![Some(YAML_SWORD), Some(YAML_SHIELD)]);
+
+        // flow lists fall back to the ids
+        let mod_file = read(
+            "flow.yml",
+            "[{id: sword, type: item, action: add, locale: {}, values: {}},\n \
+             {type: item, action: add, locale: {}, values: {}, id: shield}]",
+        );
+        assert_eq!(
+            spans(&mod_file),
+            vec![
+                Some(
+                    "sword, type: item, action: add, locale: {}, values: {}},\n \
+                     {type: item, action: add, locale: {}, values: {}, id: "
+                ),
+                Some("shield}]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn toml_mods_span_their_tables() {
+        let sword = "[[mods]]\nid = \"sword\"\ntype = \"item\"\naction = \"add\"\nlocale = {}\n\
+                     [mods.values]\nlevel = 2\n\n  ";
+        let shield = "[[ mods ]] # shield\nid = \"shield\"\ntype = \"item\"\naction = \"add\"\n\
+                      locale = {}\nvalues = {}\n";
+        let mod_file = read("mods.toml", &format!("{}{}", sword, shield));
+        assert_eq!(spans(&mod_file), vec![Some(sword), Some(shield)]);
+    }
+
+    #[test]
+    fn json5_mods_span_from_their_brace() {
+        let part = "{id: 'sword_part', type: 'item', action: 'add', locale: {}, values: {}},\n  ";
+        let shield = "{id: \"shield\", components: ['sword_part'], type: 'item', action: 'add', \
+                      locale: {}, values: {}},\n  ";
+        let sword = "{values: {}, type: 'item', action: 'add', locale: {}, id: 'sword'},\n]";
+        let mod_file = read(
+            "mods.json5",
+            &format!(
+                "[\n  // a part of the shield\n  {}{}{}",
+                part, shield, sword
+            ),
+        );
+        assert_eq!(
+            spans(&mod_file),
+            vec![Some(part), Some(shield), Some(sword)]
+        );
+    }
+
+    #[test]
+    fn script_mods_span_their_emit_call() {
+        let mod_file = read(
+            "mods.rhai",
+            "for level in 1..=2 {\n    let sword = new_mod(`sword-${level}`, \"item\");\n    emit(sword);\n}\n",
+        );
+        assert_eq!(spans(&mod_file), vec![Some("emit(sword);"); 2]);
+    }
+}
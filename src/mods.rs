@@ -1,4 +1,6 @@
+use crate::migration::FORMAT_VERSION;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,23 +10,87 @@ pub struct Mods {
     pub sqlite: PathBuf,
     #[serde(default)]
     pub resource_folder: PathBuf,
+    /// Where the Markdown changelog of each build is written.
+    #[serde(default = "default_changelog")]
+    pub changelog: PathBuf,
+    /// Locale whose text is used for locales a mod has no translation for.
+    #[serde(default = "default_fallback_locale")]
+    pub fallback_locale: String,
     pub priorities: Vec<ModPriority>,
+    /// Settings of individual packs, by pack directory or pack name.
+    #[serde(default)]
+    pub packs: BTreeMap<String, PackSettings>,
+    /// Named sets of packs to enable or disable, selected with `--profile`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Mods {
+    /// Whether a pack should be applied. The profile takes precedence over the
+    /// pack's own settings, packs are enabled unless disabled somewhere.
+    pub fn is_pack_enabled(&self, directory: &str, name: &str, profile: Option<&Profile>) -> bool {
+        let is_pack = |key: &String| key == directory || key == name;
+        if let Some(profile) = profile {
+            if profile.disable.iter().any(is_pack) {
+                return false;
+            }
+            if profile.enable.iter().any(is_pack) {
+                return true;
+            }
+        }
+        self.packs
+            .iter()
+            .filter(|(key, _)| is_pack(key))
+            .all(|(_, settings)| settings.enabled)
+    }
 }
 
 impl Default for Mods {
     fn default() -> Mods {
         Mods {
-            version: "".to_string(),
+            version: FORMAT_VERSION.to_string(),
             database: PathBuf::from("cdclient.fdb"),
             sqlite: PathBuf::from("CDServer.sqlite"),
             resource_folder: PathBuf::new(),
+            changelog: default_changelog(),
+            fallback_locale: default_fallback_locale(),
             priorities: vec![],
+            packs: BTreeMap::new(),
+            profiles: BTreeMap::new(),
         }
     }
 }
 
+fn default_changelog() -> PathBuf {
+    PathBuf::from("changelog.md")
+}
+
+fn default_fallback_locale() -> String {
+    String::from("en_US")
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModPriority {
     directory: String,
     priority: u32,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackSettings {
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// A build profile, e.g. "dev" with debug packs and "release" without them.
+/// Packs are listed by directory or by name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub enable: Vec<String>,
+    #[serde(default)]
+    pub disable: Vec<String>,
+}
@@ -0,0 +1,77 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Summary of a build, written to the file given with `--report` for tools
+/// like launchers and CI to consume.
+#[derive(Debug, Default, Serialize)]
+pub struct BuildReport {
+    pub packs: Vec<PackReport>,
+    /// Number of rows mods added to each table. Rows inserted by SQL mods
+    /// aren't counted.
+    pub rows_added: BTreeMap<String, usize>,
+    pub ids: IdReport,
+    pub locale_phrases_added: usize,
+    pub warnings: Vec<String>,
+    pub stages: Vec<StageReport>,
+    /// Stages that were skipped because their output was up to date. What
+    /// they would have done isn't counted, so `rows_added` is empty when the
+    /// database wasn't exported.
+    pub skipped_stages: Vec<String>,
+    /// Whether the whole build was skipped because nothing changed since the
+    /// last one, in which case the counts of the report are all empty.
+    pub up_to_date: bool,
+    pub total_ms: u128,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackReport {
+    pub name: String,
+    /// Directory of the pack relative to mods.json.
+    pub directory: String,
+    pub files: Vec<PathBuf>,
+    /// Number of mods applied, including mods created by other mods.
+    pub mods: usize,
+}
+
+/// The database IDs assigned to mods, split into IDs that were generated in
+/// this build and IDs that were taken from lookup.json.
+#[derive(Debug, Default, Serialize)]
+pub struct IdReport {
+    pub new: Vec<AllocatedId>,
+    pub reused: Vec<AllocatedId>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AllocatedId {
+    pub mod_id: String,
+    pub table: String,
+    pub id: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StageReport {
+    pub name: String,
+    pub ms: u128,
+}
+
+impl BuildReport {
+    /// Print a warning and record it in the report.
+    pub fn warn(&mut self, message: String) {
+        println!("Warning: {}", message);
+        self.warnings.push(message);
+    }
+
+    /// Print how long a stage took and record it in the report. Returns the
+    /// start of the next stage.
+    pub fn finish_stage(&mut self, name: &str, start: Instant) -> Instant {
+        let duration = start.elapsed();
+        println!("{}ms", duration.as_millis());
+        self.stages.push(StageReport {
+            name: name.to_string(),
+            ms: duration.as_millis(),
+        });
+        Instant::now()
+    }
+}
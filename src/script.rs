@@ -0,0 +1,150 @@
+use crate::diagnostics::{offset_of, SourceFile};
+use crate::lu_mod::Mod;
+use crate::mission::MissionOffer;
+use color_eyre::eyre::{self, eyre};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, NativeCallContext};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Run a mod generation script and return the mods it emitted, with the line
+/// of the script that emitted them.
+///
+/// Scripts are written in [Rhai](https://rhai.rs). They create mods with
+/// `new_mod(id, type)`, fill them in with the functions registered below and
+/// hand them over with `emit(mod)`, after which they are applied just like
+/// mods read from a JSON file:
+///
+/// ```rhai
+/// for level in 1..=10 {
+///     let sword = new_mod(`sword-${level}`, "item");
+///     sword.set_value("level", level);
+///     sword.set_value("damage", 2 * level + 1);
+///     sword.add_locale("en_US", `Sword of Level ${level}`);
+///     sword.link_skills([1234]);
+///     emit(sword);
+/// }
+/// ```
+pub fn run_script(source: &SourceFile) -> eyre::Result<Vec<(Mod, Option<Range<usize>>)>> {
+    let emitted = Rc::new(RefCell::new(Vec::<(Mod, Option<(usize, usize)>)>::new()));
+
+    let mut engine = Engine::new();
+    engine.register_type_with_name::<Mod>("Mod");
+    engine.register_get("id", |lu_mod: &mut Mod| lu_mod.id.clone());
+    engine.register_get("type", |lu_mod: &mut Mod| lu_mod.mod_type.clone());
+    engine.register_fn("new_mod", |id: &str, mod_type: &str| Mod {
+        id: id.to_string(),
+        mod_type: mod_type.to_string(),
+        ..Default::default()
+    });
+    engine.register_fn("set_table", |lu_mod: &mut Mod, table: &str| {
+        lu_mod.table = Some(table.to_string());
+    });
+    engine.register_fn(
+        "set_value",
+        |lu_mod: &mut Mod, key: &str, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            lu_mod.values.insert(key.to_string(), to_json(&value)?);
+            Ok(())
+        },
+    );
+    // Mirrors Mod::add_locale, setting the text of the mod's name
+    engine.register_fn(
+        "add_locale",
+        |lu_mod: &mut Mod, locale: &str, text: &str| {
+            lu_mod.locale.insert(locale.to_string(), text.to_string());
+        },
+    );
+    // Mirrors Mod::add_locale_from_value, setting localized text values like `offer`
+    engine.register_fn(
+        "add_locale",
+        |lu_mod: &mut Mod, key: &str, locale: &str, text: &str| {
+            let localized = lu_mod
+                .values
+                .entry(key.to_string())
+                .or_insert_with(|| JsonValue::Object(JsonMap::new()));
+            if !localized.is_object() {
+                *localized = JsonValue::Object(JsonMap::new());
+            }
+            if let JsonValue::Object(localized) = localized {
+                localized.insert(locale.to_string(), JsonValue::from(text));
+            }
+        },
+    );
+    // Mirrors Mod::add_component, linking a component mod by its id
+    engine.register_fn("add_component", |lu_mod: &mut Mod, component_id: &str| {
+        lu_mod.components.push(component_id.to_string());
+    });
+    // Mirrors Mod::link_skills, setting the skills linked to an item or enemy
+    engine.register_fn(
+        "link_skills",
+        |lu_mod: &mut Mod, skills: Array| -> Result<(), Box<EvalAltResult>> {
+            lu_mod.skills = skills.iter().map(to_json).collect::<Result<_, _>>()?;
+            Ok(())
+        },
+    );
+    engine.register_fn(
+        "add_item",
+        |lu_mod: &mut Mod, item: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            lu_mod.items.push(to_json(&item)?);
+            Ok(())
+        },
+    );
+    engine.register_fn(
+        "add_mission",
+        |lu_mod: &mut Mod, mission: &str, offer: bool, accept: bool| {
+            lu_mod.missions.push(MissionOffer {
+                mission: mission.to_string(),
+                offer,
+                accept,
+            });
+        },
+    );
+    // Tasks are given as object maps with the same fields as in JSON mod files
+    engine.register_fn(
+        "add_task",
+        |lu_mod: &mut Mod, task: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            lu_mod.tasks.push(rhai::serde::from_dynamic(&task)?);
+            Ok(())
+        },
+    );
+    // Mods are located at the line that emits them
+    let emit_target = emitted.clone();
+    engine.register_fn("emit", move |context: NativeCallContext, lu_mod: Mod| {
+        let position = context.call_position();
+        emit_target
+            .borrow_mut()
+            .push((lu_mod, position.line().zip(position.position())));
+    });
+
+    engine.run(&source.contents).map_err(|error| {
+        let position = error.position();
+        match (position.line(), position.position()) {
+            (Some(line), Some(column)) => source.error_at(
+                format!("Script failed: {}", error),
+                offset_of(&source.contents, line, column),
+            ),
+            _ => eyre!("Script failed: {}", error),
+        }
+    })?;
+
+    let mods = emitted
+        .borrow()
+        .iter()
+        .map(|(lu_mod, position)| {
+            let span = position.map(|(line, column)| {
+                let start = offset_of(&source.contents, line, column);
+                let end = source.contents[start..]
+                    .find('\n')
+                    .map_or(source.contents.len(), |i| start + i);
+                start..end
+            });
+            (lu_mod.clone(), span)
+        })
+        .collect();
+    Ok(mods)
+}
+
+fn to_json(value: &Dynamic) -> Result<JsonValue, Box<EvalAltResult>> {
+    rhai::serde::from_dynamic(value)
+}
@@ -0,0 +1,638 @@
+use crate::json_edit::{set_string_members, SpannedJson, SpannedKind, TextEdits};
+use crate::locale::Localization;
+use crate::lu_mod::{
+    locale_include, name_phrase_id, Mod, MISSION_TASK_PHRASE, MISSION_TEXT_PHRASES,
+};
+use crate::manifest::Manifest;
+use crate::migration::read_lookup;
+use crate::mod_file::{read_mod_file, ModFile, ModFileFormat};
+use crate::mods::Mods;
+use crate::{
+    find_packs, pack_directory_key, qualify_id, read_json, selected_profile, Options, Packs,
+    OUTPUT_LOCALE_PATH,
+};
+use color_eyre::{
+    eyre::{self, eyre, WrapErr},
+    Section,
+};
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// A localized text of a mod, which is one row of a translation file.
+struct Translatable {
+    /// The mod file, relative to mods.json.
+    file: String,
+    /// The id of the mod, as written in its file.
+    mod_id: String,
+    /// Where the text is in the mod: `locale`, `values.<key>` or
+    /// `tasks.<index>.locale`.
+    key: String,
+    /// The id of the phrase the text is added as. Contains `{}` instead of the
+    /// database ID if the mod hasn't been built yet.
+    phrase: String,
+    translations: BTreeMap<String, String>,
+}
+
+/// The formats of translation files, chosen by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TranslationFormat {
+    /// One row per text, with a column for each locale.
+    Csv,
+    /// XLIFF 1.2, with a `<file>` for each mod file and target locale.
+    Xliff,
+}
+
+impl TranslationFormat {
+    fn from_path(path: &Path) -> eyre::Result<TranslationFormat> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        match extension.as_str() {
+            "csv" => Ok(TranslationFormat::Csv),
+            "xlf" | "xliff" => Ok(TranslationFormat::Xliff),
+            _ => Err(eyre!("Unsupported translation file {}", path.display())).suggestion(
+                "Translation files need to have one of the extensions .csv, .xlf or .xliff.",
+            ),
+        }
+    }
+}
+
+/// Write every text of the mods in the enabled packs to a translation file.
+pub fn export_translations(opts: &Options, path: &Path) -> eyre::Result<()> {
+    let format = TranslationFormat::from_path(path)?;
+    let configuration = read_json::<Mods>(&opts.input).wrap_err("Failed to read mods.json")?;
+    // Default to current working dir if this fails
+    let _ = std::env::set_current_dir(opts.input.parent().unwrap());
+
+    let profile = selected_profile(&configuration, opts)?;
+    let packs = find_packs(&configuration, profile.as_ref())?;
+    let lookup = if Path::new("lookup.json").is_file() {
+        read_lookup(Path::new("lookup.json"))?.ids
+    } else {
+        BTreeMap::new()
+    };
+    let translatables = collect_translatables(&packs, &lookup)?;
+
+    // The source locale comes first, as translators read it first
+    let source_locale = &configuration.fallback_locale;
+    let mut locales = vec![source_locale.clone()];
+    for locale in read_locales()? {
+        if !locales.contains(&locale) {
+            locales.push(locale);
+        }
+    }
+
+    match format {
+        TranslationFormat::Csv => write_csv(path, &translatables, &locales)?,
+        TranslationFormat::Xliff => write_xliff(path, &translatables, &locales)?,
+    }
+    println!(
+        "Exported {} texts in {} locales to {}",
+        translatables.len(),
+        locales.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Merge the translations in a translation file into the mod files they're
+/// from. Only the translations are changed, the rest of the files is left as
+/// it is. JSON and YAML mod files can be written to, but YAML files only if
+/// they have no comments, since those would be lost.
+pub fn import_translations(opts: &Options, path: &Path) -> eyre::Result<()> {
+    let format = TranslationFormat::from_path(path)?;
+    let imported = match format {
+        TranslationFormat::Csv => read_csv(path),
+        TranslationFormat::Xliff => read_xliff(path),
+    }
+    .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let configuration = read_json::<Mods>(&opts.input).wrap_err("Failed to read mods.json")?;
+    // Default to current working dir if this fails
+    let _ = std::env::set_current_dir(opts.input.parent().unwrap());
+
+    // The manifests of the mod files, to compare texts with variables in them
+    let profile = selected_profile(&configuration, opts)?;
+    let packs = find_packs(&configuration, profile.as_ref())?;
+    let mut manifests = HashMap::<String, &Manifest>::new();
+    for (manifest_path, manifest) in &packs.enabled {
+        for file in manifest.mod_files(manifest_path.parent().unwrap())? {
+            manifests.insert(pack_directory_key(&file), manifest);
+        }
+    }
+
+    // Group by file, to write every file once
+    let mut files = BTreeMap::<String, Vec<(String, String, BTreeMap<String, String>)>>::new();
+    for ((file, mod_id, key), translations) in imported {
+        files
+            .entry(file)
+            .or_default()
+            .push((mod_id, key, translations));
+    }
+
+    let mut imported_count = 0;
+    // Included texts can share files, so files are counted by path
+    let mut changed_files = BTreeSet::<PathBuf>::new();
+    for (file, texts) in files {
+        let file_path = PathBuf::from(&file);
+        let format = ModFileFormat::from_path(&file_path)?;
+        if !matches!(format, ModFileFormat::Json | ModFileFormat::Yaml) {
+            println!(
+                "Warning: translations can't be written to {}, only to JSON and YAML mod files. \
+                Add the {} texts for it by hand.",
+                file,
+                texts.len()
+            );
+            continue;
+        }
+        let contents = std::fs::read_to_string(&file_path)
+            .wrap_err_with(|| format!("Failed to read mod file {}", file))?;
+        let dir = file_path.parent().unwrap();
+        let manifest = manifests.get(&file).copied();
+        let missing_text = |mod_id: &str, key: &str| {
+            Err(eyre!("Mod {} in {} has no text {}", mod_id, file, key)).suggestion(
+                "The mod file changed since the translations were exported, export them again.",
+            )
+        };
+
+        let output = if let ModFileFormat::Json = format {
+            // Edit the text of the file, so that its formatting is kept
+            let mods = SpannedJson::parse(&contents)
+                .wrap_err_with(|| format!("Failed to parse mod file {}", file))?;
+            let mut edits = TextEdits::default();
+            for (mod_id, key, translations) in &texts {
+                let target = match find_text_span(&mods, mod_id, key) {
+                    Some(target) => target,
+                    None => return missing_text(mod_id, key),
+                };
+                let mut changed_members = vec![];
+                for (locale, text) in translations {
+                    let existing = target.get(locale).and_then(SpannedJson::as_str);
+                    match import_text(dir, manifest, locale, existing, text)? {
+                        TextImport::Unchanged => {}
+                        TextImport::Included(include) => {
+                            imported_count += 1;
+                            changed_files.insert(include);
+                        }
+                        TextImport::Inline => {
+                            changed_members.push((locale.clone(), text.clone()));
+                            imported_count += 1;
+                        }
+                    }
+                }
+                set_string_members(&contents, target, &changed_members, &mut edits)?;
+            }
+            if edits.is_empty() {
+                continue;
+            }
+            edits.apply(&contents)
+        } else {
+            let mut mods: JsonValue = serde_yaml::from_str(&contents)?;
+            let mut changed_count = 0;
+            for (mod_id, key, translations) in &texts {
+                let target = match find_text(&mut mods, mod_id, key) {
+                    Some(target) => target,
+                    None => return missing_text(mod_id, key),
+                };
+                for (locale, text) in translations {
+                    let existing = target.get(locale).and_then(JsonValue::as_str);
+                    match import_text(dir, manifest, locale, existing, text)? {
+                        TextImport::Unchanged => {}
+                        TextImport::Included(include) => {
+                            imported_count += 1;
+                            changed_files.insert(include);
+                        }
+                        TextImport::Inline => {
+                            target.insert(locale.clone(), JsonValue::from(text.as_str()));
+                            changed_count += 1;
+                        }
+                    }
+                }
+            }
+            if changed_count == 0 {
+                continue;
+            }
+            if has_yaml_comments(&contents) {
+                println!(
+                    "Warning: {} has comments, which would be lost by writing translations to it. \
+                    Add the {} translations for it by hand.",
+                    file, changed_count
+                );
+                continue;
+            }
+            imported_count += changed_count;
+            serde_yaml::to_string(&mods)?
+        };
+        std::fs::write(&file_path, output)
+            .wrap_err_with(|| format!("Failed to write mod file {}", file))?;
+        changed_files.insert(file_path);
+    }
+    println!(
+        "Imported {} translations into {} files",
+        imported_count,
+        changed_files.len()
+    );
+    Ok(())
+}
+
+/// What importing a translation does.
+enum TextImport {
+    /// The mod already has this text.
+    Unchanged,
+    /// The text was written to the file the mod includes it from.
+    Included(PathBuf),
+    /// The text needs to be set in the mod file.
+    Inline,
+}
+
+/// Import the translation `text` for `locale` of a text that currently is
+/// `existing` in the mod file. Texts included from a file are written to that
+/// file right away.
+fn import_text(
+    dir: &Path,
+    manifest: Option<&Manifest>,
+    locale: &str,
+    existing: Option<&str>,
+    text: &str,
+) -> eyre::Result<TextImport> {
+    let existing = match existing {
+        Some(existing) => existing,
+        None => return Ok(TextImport::Inline),
+    };
+    if let Some(include) = locale_include(dir, locale, existing) {
+        if read_included_text(&include).ok().as_deref() == Some(text) {
+            return Ok(TextImport::Unchanged);
+        }
+        if let Some(parent) = include.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&include, format!("{}\n", text))
+            .wrap_err_with(|| format!("Failed to write included text {}", include.display()))?;
+        return Ok(TextImport::Included(include));
+    }
+    // Texts were exported with variables substituted, which must stay variables
+    let substituted = manifest.and_then(|manifest| manifest.substitute_str(existing).ok());
+    if existing == text || substituted.as_deref() == Some(text) {
+        return Ok(TextImport::Unchanged);
+    }
+    Ok(TextImport::Inline)
+}
+
+/// Whether a YAML file may have comments. Can be wrong about a `#` in a
+/// string, which only means the file isn't written to.
+fn has_yaml_comments(contents: &str) -> bool {
+    contents
+        .lines()
+        .any(|line| line.trim_start().starts_with('#') || line.contains(" #"))
+}
+
+/// Read the mod files of all enabled packs and list their texts.
+fn collect_translatables(
+    packs: &Packs,
+    lookup: &BTreeMap<String, i32>,
+) -> eyre::Result<Vec<Translatable>> {
+    let mut translatables = Vec::new();
+    for (manifest_path, manifest) in &packs.enabled {
+        for file in manifest.mod_files(manifest_path.parent().unwrap())? {
+            let ModFile { mods, .. } = read_mod_file(&file)?;
+            let dir = file.parent().unwrap();
+            let file = pack_directory_key(&file);
+            for (mut lu_mod, _) in mods {
+                let id = qualify_id(&packs.names, &manifest.name, &lu_mod.id);
+                // Translators get the texts as they end up in the game
+                manifest
+                    .substitute_variables(&mut lu_mod)
+                    .wrap_err_with(|| format!("Failed to substitute variables in {}", file))?;
+                for (key, phrase_id, owner_id, mut translations) in texts_of_mod(&lu_mod, &id) {
+                    // Translators get the text of included files, missing
+                    // files are texts that haven't been translated yet
+                    for (locale, text) in translations.clone() {
+                        if let Some(include) = locale_include(dir, &locale, &text) {
+                            match read_included_text(&include) {
+                                Ok(included) => translations.insert(locale, included),
+                                Err(_) => translations.remove(&locale),
+                            };
+                        }
+                    }
+                    let phrase = match lookup.get(&owner_id) {
+                        Some(database_id) => phrase_id.replace("{}", &database_id.to_string()),
+                        None => phrase_id.to_string(),
+                    };
+                    translatables.push(Translatable {
+                        file: file.clone(),
+                        mod_id: lu_mod.id.clone(),
+                        key,
+                        phrase,
+                        translations,
+                    });
+                }
+            }
+        }
+    }
+    Ok(translatables)
+}
+
+/// The texts of a mod: their key, the phrase they're added as, the id of the
+/// mod whose database ID is in the phrase id and the translations.
+fn texts_of_mod(lu_mod: &Mod, id: &str) -> Vec<(String, String, String, BTreeMap<String, String>)> {
+    let mut texts = Vec::new();
+    // Locale mods give the id of the phrase they add or change
+    let phrase_id = match lu_mod.mod_type.as_str() {
+        "locale" if lu_mod.action != "remove" => lu_mod
+            .values
+            .get("phrase")
+            .and_then(JsonValue::as_str)
+            .map(String::from),
+        mod_type => name_phrase_id(mod_type).map(String::from),
+    };
+    if let Some(phrase_id) = phrase_id {
+        if !lu_mod.locale.is_empty() {
+            texts.push((
+                String::from("locale"),
+                phrase_id,
+                id.to_string(),
+                lu_mod.locale.clone(),
+            ));
+        }
+    }
+    if lu_mod.mod_type == "mission" {
+        for (phrase_id, key) in MISSION_TEXT_PHRASES {
+            if let Some(JsonValue::Object(localized)) = lu_mod.values.get(*key) {
+                let translations = localized
+                    .iter()
+                    .filter_map(|(locale, text)| Some((locale.clone(), text.as_str()?.to_string())))
+                    .collect();
+                texts.push((
+                    format!("values.{}", key),
+                    phrase_id.to_string(),
+                    id.to_string(),
+                    translations,
+                ));
+            }
+        }
+        for (index, task) in lu_mod.tasks.iter().enumerate() {
+            if !task.locale.is_empty() {
+                texts.push((
+                    format!("tasks.{}.locale", index),
+                    MISSION_TASK_PHRASE.to_string(),
+                    format!("{}:tasks:{}", id, index),
+                    task.locale.clone(),
+                ));
+            }
+        }
+    }
+    texts
+}
+
+/// The text of a file included by a locale text, as the build reads it.
+fn read_included_text(path: &Path) -> eyre::Result<String> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(text.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+/// The locales of the client, read from the copy of locale.xml next to
+/// mods.json if there is one.
+fn read_locales() -> eyre::Result<Vec<String>> {
+    let path = if Path::new("locale.xml").is_file() {
+        Path::new("locale.xml")
+    } else {
+        Path::new(OUTPUT_LOCALE_PATH)
+    };
+    let localization = Localization::read(path)
+        .wrap_err_with(|| format!("Failed to read locales from {}", path.display()))?;
+    Ok(localization.locales)
+}
+
+/// Find the object holding the translations of a text in the parsed mods of
+/// a mod file.
+fn find_text<'a>(
+    mods: &'a mut JsonValue,
+    mod_id: &str,
+    key: &str,
+) -> Option<&'a mut serde_json::Map<String, JsonValue>> {
+    let mut value = mods
+        .as_array_mut()?
+        .iter_mut()
+        .find(|lu_mod| lu_mod.get("id").and_then(JsonValue::as_str) == Some(mod_id))?;
+    for part in key.split('.') {
+        value = match part.parse::<usize>() {
+            Ok(index) => value.get_mut(index)?,
+            Err(_) => value.get_mut(part)?,
+        };
+    }
+    value.as_object_mut()
+}
+
+/// Like `find_text`, for the text of a JSON file.
+fn find_text_span<'a>(mods: &'a SpannedJson, mod_id: &str, key: &str) -> Option<&'a SpannedJson> {
+    let mut value = match &mods.kind {
+        SpannedKind::Array(mods) => mods
+            .iter()
+            .find(|lu_mod| lu_mod.get("id").and_then(SpannedJson::as_str) == Some(mod_id))?,
+        _ => return None,
+    };
+    for part in key.split('.') {
+        value = match part.parse::<usize>() {
+            Ok(index) => value.index(index)?,
+            Err(_) => value.get(part)?,
+        };
+    }
+    match value.kind {
+        SpannedKind::Object(_) => Some(value),
+        _ => None,
+    }
+}
+
+type ImportedTexts = BTreeMap<(String, String, String), BTreeMap<String, String>>;
+
+fn write_csv(path: &Path, translatables: &[Translatable], locales: &[String]) -> eyre::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    let mut header = vec!["mod", "file", "key", "phrase"];
+    header.extend(locales.iter().map(String::as_str));
+    writer.write_record(&header)?;
+    for translatable in translatables {
+        let mut record = vec![
+            translatable.mod_id.as_str(),
+            translatable.file.as_str(),
+            translatable.key.as_str(),
+            translatable.phrase.as_str(),
+        ];
+        for locale in locales {
+            record.push(
+                translatable
+                    .translations
+                    .get(locale)
+                    .map(String::as_str)
+                    .unwrap_or_default(),
+            );
+        }
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a CSV file written by `write_csv`. Empty cells are left out.
+fn read_csv(path: &Path) -> eyre::Result<ImportedTexts> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| eyre!("Missing column {}", name))
+    };
+    let (mod_column, file_column, key_column) = (column("mod")?, column("file")?, column("key")?);
+
+    let mut imported = ImportedTexts::new();
+    for record in reader.records() {
+        let record = record?;
+        let translations = imported
+            .entry((
+                record[file_column].to_string(),
+                record[mod_column].to_string(),
+                record[key_column].to_string(),
+            ))
+            .or_default();
+        for (header, cell) in headers.iter().zip(record.iter()) {
+            if !cell.is_empty() && !matches!(header, "mod" | "file" | "key" | "phrase") {
+                translations.insert(header.to_string(), cell.to_string());
+            }
+        }
+    }
+    Ok(imported)
+}
+
+/// XLIFF identifies languages with a dash instead of an underscore.
+fn xliff_language(locale: &str) -> String {
+    locale.replace('_', "-")
+}
+
+/// Write an XLIFF 1.2 file, with a `<file>` for each mod file and target
+/// locale. Each text is a `<trans-unit>` with the id `<mod id>|<key>`.
+fn write_xliff(
+    path: &Path,
+    translatables: &[Translatable],
+    locales: &[String],
+) -> eyre::Result<()> {
+    let mut writer = Writer::new_with_indent(BufWriter::new(std::fs::File::create(path)?), b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
+    writer.write_event(Event::Start(
+        BytesStart::borrowed_name(b"xliff").with_attributes(vec![
+            ("version", "1.2"),
+            ("xmlns", "urn:oasis:names:tc:xliff:document:1.2"),
+        ]),
+    ))?;
+
+    let source_locale = &locales[0];
+    let mut files = Vec::<&str>::new();
+    for translatable in translatables {
+        if !files.contains(&translatable.file.as_str()) {
+            files.push(&translatable.file);
+        }
+    }
+    for target_locale in &locales[1..] {
+        for file in &files {
+            let source_language = xliff_language(source_locale);
+            let target_language = xliff_language(target_locale);
+            writer.write_event(Event::Start(
+                BytesStart::borrowed_name(b"file").with_attributes(vec![
+                    ("original", *file),
+                    ("source-language", source_language.as_str()),
+                    ("target-language", target_language.as_str()),
+                    ("datatype", "plaintext"),
+                ]),
+            ))?;
+            writer.write_event(Event::Start(BytesStart::borrowed_name(b"body")))?;
+            for translatable in translatables.iter().filter(|t| t.file == *file) {
+                let id = format!("{}|{}", translatable.mod_id, translatable.key);
+                writer.write_event(Event::Start(
+                    BytesStart::borrowed_name(b"trans-unit").with_attributes(vec![
+                        ("id", id.as_str()),
+                        ("resname", translatable.phrase.as_str()),
+                    ]),
+                ))?;
+                let source = translatable
+                    .translations
+                    .get(source_locale)
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                write_text_element(&mut writer, b"source", source)?;
+                if let Some(target) = translatable.translations.get(target_locale) {
+                    write_text_element(&mut writer, b"target", target)?;
+                }
+                writer.write_event(Event::End(BytesEnd::borrowed(b"trans-unit")))?;
+            }
+            writer.write_event(Event::End(BytesEnd::borrowed(b"body")))?;
+            writer.write_event(Event::End(BytesEnd::borrowed(b"file")))?;
+        }
+    }
+    writer.write_event(Event::End(BytesEnd::borrowed(b"xliff")))?;
+    Ok(())
+}
+
+fn write_text_element<W: std::io::Write>(
+    writer: &mut Writer<W>,
+    name: &[u8],
+    text: &str,
+) -> eyre::Result<()> {
+    writer.write_event(Event::Start(BytesStart::borrowed_name(name)))?;
+    writer.write_event(Event::Text(BytesText::from_plain_str(text)))?;
+    writer.write_event(Event::End(BytesEnd::borrowed(name)))?;
+    Ok(())
+}
+
+/// Read the `<target>`s of an XLIFF file written by `write_xliff`.
+fn read_xliff(path: &Path) -> eyre::Result<ImportedTexts> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut reader = Reader::from_str(&contents);
+    let mut buffer = Vec::new();
+
+    let mut imported = ImportedTexts::new();
+    let mut file = String::new();
+    let mut locale = String::new();
+    let mut unit_id = String::new();
+    let mut in_target = false;
+    loop {
+        match reader.read_event(&mut buffer)? {
+            Event::Start(element) => match element.name() {
+                b"file" | b"trans-unit" => {
+                    for attribute in element.attributes() {
+                        let attribute = attribute?;
+                        let value = attribute.unescape_and_decode_value(&reader)?;
+                        match attribute.key {
+                            b"original" => file = value,
+                            b"target-language" => locale = value.replace('-', "_"),
+                            b"id" => unit_id = value,
+                            _ => {}
+                        }
+                    }
+                }
+                b"target" => in_target = true,
+                _ => {}
+            },
+            Event::Text(text) if in_target => {
+                let (mod_id, key) = unit_id
+                    .rsplit_once('|')
+                    .ok_or_else(|| eyre!("Invalid trans-unit id {}", unit_id))?;
+                let text = text.unescape_and_decode(&reader)?;
+                if !text.is_empty() {
+                    imported
+                        .entry((file.clone(), mod_id.to_string(), key.to_string()))
+                        .or_default()
+                        .insert(locale.clone(), text);
+                }
+            }
+            Event::End(element) if element.name() == b"target" => in_target = false,
+            Event::Eof => break,
+            _ => {}
+        }
+        buffer.clear();
+    }
+    Ok(imported)
+}
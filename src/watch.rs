@@ -0,0 +1,111 @@
+use crate::{build, find_mods_dirs, Options};
+use color_eyre::eyre;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+/// Build, then rebuild every time mods.json, a manifest, a mod file, an asset
+/// or a file included by a mod changes. Failed builds are printed instead of
+/// ending the watch, so mods can be fixed while it runs.
+///
+/// The whole directory of mods.json is watched, but the outputs written to it,
+/// like lookup.json and CDServer.sqlite, don't trigger a rebuild.
+pub fn watch(opts: &Options, validate_only: bool) -> eyre::Result<()> {
+    let mods_json = opts.input.clone();
+    let root = mods_json.parent().unwrap().to_path_buf();
+
+    // The watcher lives across builds, so files saved during a build aren't missed
+    let (sender, receiver) = channel();
+    let mut watcher = watcher(sender, Duration::from_millis(500))?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+    let mut includes = Vec::new();
+    let mut watched_includes = Vec::<PathBuf>::new();
+    loop {
+        match build(opts, validate_only) {
+            Ok(build_includes) => includes = build_includes,
+            Err(error) => eprintln!("\nError: {:?}", error),
+        }
+
+        // Packs may have been added or removed since the last build
+        let mut pack_dirs = Vec::new();
+        find_mods_dirs(&root, &mut pack_dirs)?;
+
+        // Included files outside the directory of mods.json are watched one by one
+        let outside_includes = includes
+            .iter()
+            .filter(|include| !include.starts_with(&root) && include.is_file())
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        for include in &watched_includes {
+            if !outside_includes.contains(include) {
+                let _ = watcher.unwatch(include);
+            }
+        }
+        for include in &outside_includes {
+            if !watched_includes.contains(include) {
+                watcher.watch(include, RecursiveMode::NonRecursive)?;
+            }
+        }
+        watched_includes = outside_includes;
+
+        // Rebuild right away if an input changed during the build
+        let changed_during_build = receiver
+            .try_iter()
+            .filter_map(changed_path)
+            .filter(|path| is_input(path, &mods_json, &pack_dirs, &includes))
+            .collect::<Vec<PathBuf>>();
+        let changed_path = match changed_during_build.into_iter().next() {
+            Some(path) => path,
+            None => {
+                println!(
+                    "\nWatching {} for changes, press Ctrl+C to stop.",
+                    root.display()
+                );
+                loop {
+                    if let Some(path) = changed_path(receiver.recv()?) {
+                        if is_input(&path, &mods_json, &pack_dirs, &includes) {
+                            break path;
+                        }
+                    }
+                }
+            }
+        };
+
+        // Start every build on a clear screen
+        print!("\x1B[2J\x1B[1;1H");
+        println!(
+            "{} changed, {}.\n",
+            changed_path.display(),
+            if validate_only {
+                "validating"
+            } else {
+                "rebuilding"
+            }
+        );
+    }
+}
+
+/// The file an event is about, if it changed the file.
+fn changed_path(event: DebouncedEvent) -> Option<PathBuf> {
+    match event {
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Remove(path)
+        | DebouncedEvent::Rename(_, path) => Some(path),
+        _ => None,
+    }
+}
+
+/// Whether a changed file is an input of the build. Hidden files, like the
+/// swap files of editors, are ignored.
+fn is_input(path: &Path, mods_json: &Path, pack_dirs: &[PathBuf], includes: &[PathBuf]) -> bool {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    if file_name.starts_with('.') {
+        return false;
+    }
+    path == mods_json
+        || file_name == "manifest.json"
+        || pack_dirs.iter().any(|dir| path.starts_with(dir))
+        || includes.iter().any(|include| include == path)
+}